@@ -0,0 +1,1348 @@
+use std::{cmp::Ordering, collections::BTreeMap, io::Write};
+
+use dashu_float::{
+    ops::{Abs, SquareRoot},
+    DBig, FBig,
+};
+use dashu_int::{ops::Gcd, IBig, UBig};
+use dashu_ratio::RBig;
+
+use crate::value::{
+    absolute_value, add, as_real, create_real, divide, multiply, numeric_ordering, rational_value,
+    safe_power, subtract, EvalContext, Function, NativeFunction, OverflowMode, Rounding,
+    RuntimeError, Value,
+};
+
+fn abs(
+    _: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    absolute_value(&arguments[0])
+}
+
+/// `assert(condition, message)` returns `condition` if it is true; the message is optional.
+fn assert(
+    _: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    match (&arguments[0], arguments.get(1)) {
+        (Value::Bool(true), _) => Ok(Value::Bool(true)),
+        (Value::Bool(false), None) => Err(RuntimeError::AssertionFailed {
+            message: "condition is false".to_string(),
+        }),
+        (Value::Bool(false), Some(Value::Str(message))) => Err(RuntimeError::AssertionFailed {
+            message: message.clone(),
+        }),
+        (Value::Bool(false), Some(other)) | (other, _) => Err(RuntimeError::InvalidType {
+            found: other.type_name(),
+            operation: "assert".to_string(),
+        }),
+    }
+}
+
+/// `assert_eq(actual, expected)` returns `actual` if it equals `expected`. Numbers are compared
+/// by value, and values of different kinds are simply different.
+fn assert_eq(
+    context: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    let (actual, expected) = (&arguments[0], &arguments[1]);
+    if actual == expected {
+        Ok(actual.clone())
+    } else {
+        Err(RuntimeError::AssertionFailed {
+            message: format!(
+                "expected {}, found {}",
+                expected.display(&context.display),
+                actual.display(&context.display)
+            ),
+        })
+    }
+}
+
+/// Wraps a function so that calls with arguments it has seen before return the earlier result.
+/// Each call to `memoize` starts a fresh cache.
+fn memoize(
+    context: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    match &arguments[0] {
+        Value::Function(function) => {
+            context.memo_tables.push(BTreeMap::new());
+            Ok(Value::Function(Function {
+                memo_table: Some(context.memo_tables.len() - 1),
+                ..function.clone()
+            }))
+        }
+        other => Err(RuntimeError::InvalidType {
+            found: other.type_name(),
+            operation: "memoize".to_string(),
+        }),
+    }
+}
+
+fn in_range(
+    _: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    let (value, low, high) = (&arguments[0], &arguments[1], &arguments[2]);
+    let exclusive = match arguments.get(3) {
+        None => false,
+        Some(Value::Bool(exclusive)) => *exclusive,
+        Some(other) => {
+            return Err(RuntimeError::InvalidType {
+                found: other.type_name(),
+                operation: "inRange".to_string(),
+            })
+        }
+    };
+    if numeric_ordering(low, high, "inRange")?.is_gt() {
+        return Err(RuntimeError::InvalidArgument {
+            function: "inRange".to_string(),
+            reason: "lower bound is greater than upper bound".to_string(),
+        });
+    }
+    let from_low = numeric_ordering(value, low, "inRange")?;
+    let from_high = numeric_ordering(value, high, "inRange")?;
+    Ok(Value::Bool(if exclusive {
+        from_low.is_gt() && from_high.is_lt()
+    } else {
+        from_low.is_ge() && from_high.is_le()
+    }))
+}
+
+/// Returns `chosen`, one of `arguments`, as a Decimal if any of the arguments is.
+fn promote_alongside(chosen: &Value, arguments: &[Value]) -> Value {
+    let any_real = arguments
+        .iter()
+        .any(|argument| matches!(argument, Value::Real(_)));
+    match chosen {
+        Value::SmallInt(_) | Value::Rational(_) if any_real => {
+            Value::Real(as_real(chosen).unwrap())
+        }
+        chosen => chosen.clone(),
+    }
+}
+
+/// Picks the argument that `preferred` orders first. The result is a Decimal if any argument is.
+fn extremum(arguments: &[Value], name: &str, preferred: Ordering) -> Result<Value, RuntimeError> {
+    let mut best = &arguments[0];
+    for argument in &arguments[1..] {
+        if numeric_ordering(argument, best, name)? == preferred {
+            best = argument;
+        }
+    }
+    Ok(promote_alongside(best, arguments))
+}
+
+/// `clamp(x, low, high)`: the closest value to `x` between the bounds, which are inclusive.
+/// The result is a Decimal if any argument is.
+fn clamp(
+    _: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    let (value, low, high) = (&arguments[0], &arguments[1], &arguments[2]);
+    if numeric_ordering(low, high, "clamp")?.is_gt() {
+        return Err(RuntimeError::InvalidArgument {
+            function: "clamp".to_string(),
+            reason: "lower bound is greater than upper bound".to_string(),
+        });
+    }
+    let clamped = if numeric_ordering(value, low, "clamp")?.is_lt() {
+        low
+    } else if numeric_ordering(value, high, "clamp")?.is_gt() {
+        high
+    } else {
+        value
+    };
+    Ok(promote_alongside(clamped, arguments))
+}
+
+fn min(
+    _: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    extremum(arguments, "min", Ordering::Less)
+}
+
+fn max(
+    _: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    extremum(arguments, "max", Ordering::Greater)
+}
+
+/// The principal square root, which is Complex for negative and Complex arguments.
+fn sqrt(
+    _: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    let zero = create_real(0);
+    Ok(match &arguments[0] {
+        Value::Complex { re, im } => {
+            let modulus = (re * re + im * im).sqrt();
+            let two = create_real(2);
+            let imaginary = ((&modulus - re) / &two).sqrt();
+            Value::Complex {
+                re: ((modulus + re) / two).sqrt(),
+                im: if im < &zero { -imaginary } else { imaginary },
+            }
+        }
+        value => match as_real(value) {
+            Some(value) if value < zero => Value::Complex {
+                re: zero,
+                im: (-value).sqrt(),
+            },
+            Some(value) => Value::Real(value.sqrt()),
+            None => {
+                return Err(RuntimeError::InvalidType {
+                    found: value.type_name(),
+                    operation: "sqrt".to_string(),
+                })
+            }
+        },
+    })
+}
+
+/// The real `degree`th root of `value`, exact when an integer is a perfect power. Even roots
+/// of negative numbers are rejected rather than made Complex.
+fn real_root(function: &str, degree: i64, value: &Value) -> Result<Value, RuntimeError> {
+    let invalid = |reason: &str| RuntimeError::InvalidArgument {
+        function: function.to_string(),
+        reason: reason.to_string(),
+    };
+    let degree = usize::try_from(degree)
+        .ok()
+        .filter(|&degree| degree > 0)
+        .ok_or_else(|| invalid("the degree must be a positive integer"))?;
+    let real = as_real(value).ok_or_else(|| RuntimeError::InvalidType {
+        found: value.type_name(),
+        operation: function.to_string(),
+    })?;
+    let zero = create_real(0);
+    if real < zero && degree % 2 == 0 {
+        return Err(invalid("even root of a negative number"));
+    }
+    if let Value::SmallInt(integer) = value {
+        let root = IBig::from(*integer).nth_root(degree);
+        if root.pow(degree) == IBig::from(*integer) {
+            return Ok(integer_value(root));
+        }
+    }
+    if real == zero {
+        return Ok(Value::Real(zero));
+    }
+    let exponent = create_real(1) / create_real(degree as i64);
+    Ok(Value::Real(if real < zero {
+        -(-real).powf(&exponent)
+    } else {
+        real.powf(&exponent)
+    }))
+}
+
+/// `root(n, x)` is the real nth root of `x`.
+fn root(
+    _: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    match &arguments[0] {
+        Value::SmallInt(degree) => real_root("root", *degree, &arguments[1]),
+        other => Err(RuntimeError::InvalidType {
+            found: other.type_name(),
+            operation: "root".to_string(),
+        }),
+    }
+}
+
+/// The real cube root, which is negative for negative arguments.
+fn cbrt(
+    _: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    real_root("cbrt", 3, &arguments[0])
+}
+
+/// The real value of an argument to a function that doesn't support Complex ones.
+fn real_argument(function: &str, value: &Value) -> Result<FBig, RuntimeError> {
+    match value {
+        Value::Complex { .. } => None,
+        value => as_real(value),
+    }
+    .ok_or_else(|| RuntimeError::InvalidType {
+        found: value.type_name(),
+        operation: function.to_string(),
+    })
+}
+
+// These use `exp_m1` rather than `exp` so that small arguments keep their precision.
+
+fn sinh(
+    _: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    let x = real_argument("sinh", &arguments[0])?;
+    Ok(Value::Real((x.exp_m1() - (-x).exp_m1()) / create_real(2)))
+}
+
+fn cosh(
+    _: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    let x = real_argument("cosh", &arguments[0])?;
+    Ok(Value::Real((x.exp() + (-x).exp()) / create_real(2)))
+}
+
+fn tanh(
+    _: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    let x = real_argument("tanh", &arguments[0])?;
+    let two = create_real(2);
+    let growth = (x * &two).exp_m1();
+    Ok(Value::Real(&growth / (&growth + two)))
+}
+
+/// The arctangent in radians. The argument is brought within 1 and then halved a few times,
+/// so that the Taylor series needs only a handful of terms.
+fn arctangent(x: FBig) -> FBig {
+    let one = create_real(1);
+    if x > one || x < -&one {
+        let half_pi = arctangent(one.clone()) * create_real(2);
+        let complement = arctangent(&one / &x);
+        return if x > one {
+            half_pi - complement
+        } else {
+            -half_pi - complement
+        };
+    }
+    const HALVINGS: u32 = 4;
+    let mut x = x;
+    for _ in 0..HALVINGS {
+        x = &x / (&one + (&one + x.sqr()).sqrt());
+    }
+    let square = -x.sqr();
+    let mut sum = x.clone();
+    let mut power = x;
+    let mut denominator = 1;
+    loop {
+        power *= &square;
+        denominator += 2;
+        let term = &power / create_real(denominator);
+        if term.repr().is_zero() || term.clone().abs() < sum.ulp() {
+            break;
+        }
+        sum += term;
+    }
+    sum * create_real(1 << HALVINGS)
+}
+
+/// The arcsine of a real in `[-1, 1]`, between -π/2 and π/2.
+fn arcsine(function: &str, x: FBig) -> Result<FBig, RuntimeError> {
+    let one = create_real(1);
+    if x > one || x < -&one {
+        return Err(RuntimeError::InvalidArgument {
+            function: function.to_string(),
+            reason: format!("{} is outside the domain [-1, 1]", Value::Real(x)),
+        });
+    }
+    let half_pi = arctangent(one.clone()) * create_real(2);
+    Ok(if x == one {
+        half_pi
+    } else if x == -&one {
+        -half_pi
+    } else {
+        arctangent(&x / (one - x.sqr()).sqrt())
+    })
+}
+
+fn asin(
+    _: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    let x = real_argument("asin", &arguments[0])?;
+    Ok(Value::Real(arcsine("asin", x)?))
+}
+
+fn acos(
+    _: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    let x = real_argument("acos", &arguments[0])?;
+    let half_pi = arctangent(create_real(1)) * create_real(2);
+    Ok(Value::Real(half_pi - arcsine("acos", x)?))
+}
+
+fn atan(
+    _: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    Ok(Value::Real(arctangent(real_argument(
+        "atan",
+        &arguments[0],
+    )?)))
+}
+
+/// `atan2(y, x)` is the angle of the point `(x, y)`, between -π and π; `atan2(0, 0)` is 0.
+fn atan2(
+    _: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    let y = real_argument("atan2", &arguments[0])?;
+    let x = real_argument("atan2", &arguments[1])?;
+    let zero = create_real(0);
+    let pi = arctangent(create_real(1)) * create_real(4);
+    Ok(Value::Real(if x == zero {
+        match y.cmp(&zero) {
+            Ordering::Greater => pi / create_real(2),
+            Ordering::Less => -pi / create_real(2),
+            Ordering::Equal => zero,
+        }
+    } else {
+        let angle = arctangent(&y / &x);
+        match (x > zero, y < zero) {
+            (true, _) => angle,
+            (false, false) => angle + pi,
+            (false, true) => angle - pi,
+        }
+    }))
+}
+
+/// The most decimal places `round_to` accepts. Rounding costs time in proportion to the
+/// places asked for, and Decimals hold far fewer than this.
+const MAX_ROUND_TO_DIGITS: usize = 1000;
+
+/// `round_to(x, digits)` rounds to `digits` decimal places, with ties away from zero. The
+/// result is always a Decimal, so `round_to(2.5, 0)` is `3`.
+fn round_to(
+    _: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    let x = real_argument("round_to", &arguments[0])?;
+    let digits = match &arguments[1] {
+        Value::SmallInt(digits) => {
+            usize::try_from(*digits).map_err(|_| RuntimeError::InvalidArgument {
+                function: "round_to".to_string(),
+                reason: "the number of digits must not be negative".to_string(),
+            })?
+        }
+        other => {
+            return Err(RuntimeError::InvalidType {
+                found: other.type_name(),
+                operation: "round_to".to_string(),
+            })
+        }
+    };
+    if digits > MAX_ROUND_TO_DIGITS {
+        return Err(RuntimeError::InvalidArgument {
+            function: "round_to".to_string(),
+            reason: format!(
+                "the number of digits must be at most {}",
+                MAX_ROUND_TO_DIGITS
+            ),
+        });
+    }
+    // The decimal expansion of a binary real is exact, so only the final rounding is inexact.
+    let decimal = x.to_decimal().value();
+    let repr = decimal.repr();
+    let scaled = DBig::from_parts(
+        repr.significand().clone(),
+        repr.exponent() + digits as isize,
+    );
+    let rounded = RBig::from_parts(
+        scaled.round().to_int().value(),
+        UBig::from(10u8).pow(digits),
+    );
+    Ok(Value::Real(as_real(&Value::Rational(rounded)).unwrap()))
+}
+
+/// `type(x)` names the type of any value, such as `"SmallInt"` or `"Function"`.
+fn type_of(
+    _: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    Ok(Value::Str(arguments[0].type_name()))
+}
+
+/// The magnitudes of two SmallInt arguments, which can't overflow even for `i64::MIN`.
+fn integer_magnitudes(arguments: &[Value], function: &str) -> Result<(u64, u64), RuntimeError> {
+    match (&arguments[0], &arguments[1]) {
+        (Value::SmallInt(a), Value::SmallInt(b)) => Ok((a.unsigned_abs(), b.unsigned_abs())),
+        (Value::SmallInt(_), other) | (other, _) => Err(RuntimeError::InvalidType {
+            found: other.type_name(),
+            operation: function.to_string(),
+        }),
+    }
+}
+
+fn integer_argument(argument: &Value, function: &str) -> Result<i64, RuntimeError> {
+    match argument {
+        Value::SmallInt(integer) => Ok(*integer),
+        other => Err(RuntimeError::InvalidType {
+            found: other.type_name(),
+            operation: function.to_string(),
+        }),
+    }
+}
+
+fn is_even(
+    _: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    Ok(Value::Bool(
+        integer_argument(&arguments[0], "is_even")? % 2 == 0,
+    ))
+}
+
+fn is_odd(
+    _: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    Ok(Value::Bool(
+        integer_argument(&arguments[0], "is_odd")? % 2 != 0,
+    ))
+}
+
+/// `divides(a, b)` is true if `b` is a multiple of `a`. Zero only divides zero, since zero is
+/// the only multiple of zero.
+fn divides(
+    _: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    let a = integer_argument(&arguments[0], "divides")?;
+    let b = integer_argument(&arguments[1], "divides")?;
+    Ok(Value::Bool(if a == 0 {
+        b == 0
+    } else {
+        b.wrapping_rem(a) == 0
+    }))
+}
+
+fn euclid(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// A whole number as a SmallInt, or as a Decimal if it is out of range.
+fn integer_value(integer: impl Into<IBig>) -> Value {
+    rational_value(RBig::from(integer.into()))
+}
+
+/// The greatest common divisor of the magnitudes of two SmallInts; `gcd(0, 0)` is 0.
+fn gcd(
+    _: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    let (a, b) = integer_magnitudes(arguments, "gcd")?;
+    Ok(integer_value(euclid(a, b)))
+}
+
+/// The least common multiple of the magnitudes of two SmallInts, or 0 if either is 0.
+fn lcm(
+    _: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    let (a, b) = integer_magnitudes(arguments, "lcm")?;
+    if a == 0 || b == 0 {
+        return Ok(Value::SmallInt(0));
+    }
+    Ok(integer_value(u128::from(a / euclid(a, b)) * u128::from(b)))
+}
+
+/// The magnitudes of a non-empty list of SmallInts.
+fn integer_list_magnitudes(argument: &Value, function: &str) -> Result<Vec<u64>, RuntimeError> {
+    let elements = list_argument(argument, function)?;
+    if elements.is_empty() {
+        return Err(RuntimeError::InvalidArgument {
+            function: function.to_string(),
+            reason: "the list is empty".to_string(),
+        });
+    }
+    elements
+        .iter()
+        .map(|element| integer_argument(element, function).map(i64::unsigned_abs))
+        .collect()
+}
+
+/// `gcd` of every element of a non-empty list; zeros are ignored, so a list of zeros gives 0.
+fn gcd_list(
+    _: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    let magnitudes = integer_list_magnitudes(&arguments[0], "gcdList")?;
+    Ok(integer_value(magnitudes.into_iter().fold(0, euclid)))
+}
+
+/// `lcm` of every element of a non-empty list, which is 0 if any of them is 0.
+fn lcm_list(
+    _: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    let magnitudes = integer_list_magnitudes(&arguments[0], "lcmList")?;
+    if magnitudes.contains(&0) {
+        return Ok(Value::SmallInt(0));
+    }
+    let lcm = magnitudes
+        .into_iter()
+        .map(UBig::from)
+        .fold(UBig::ONE, |lcm, magnitude| {
+            let divisor = (&lcm).gcd(&magnitude);
+            lcm / divisor * magnitude
+        });
+    Ok(integer_value(lcm))
+}
+
+fn list_argument<'a>(argument: &'a Value, function: &str) -> Result<&'a [Value], RuntimeError> {
+    match argument {
+        Value::List(elements) => Ok(elements),
+        other => Err(RuntimeError::InvalidType {
+            found: other.type_name(),
+            operation: function.to_string(),
+        }),
+    }
+}
+
+fn map(
+    context: &mut EvalContext,
+    variables: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    let elements = list_argument(&arguments[1], "map")?;
+    Ok(Value::List(
+        elements
+            .iter()
+            .map(|element| Value::apply(context, variables, &arguments[0], vec![element.clone()]))
+            .collect::<Result<_, _>>()?,
+    ))
+}
+
+/// Keeps the elements for which the predicate returns true; it must always return a Bool.
+fn filter(
+    context: &mut EvalContext,
+    variables: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    let mut kept = Vec::new();
+    for element in list_argument(&arguments[1], "filter")? {
+        match Value::apply(context, variables, &arguments[0], vec![element.clone()])? {
+            Value::Bool(true) => kept.push(element.clone()),
+            Value::Bool(false) => {}
+            other => {
+                return Err(RuntimeError::InvalidType {
+                    found: other.type_name(),
+                    operation: "filter".to_string(),
+                })
+            }
+        }
+    }
+    Ok(Value::List(kept))
+}
+
+/// The number of elements in a list, or of characters (Unicode scalar values) in a string.
+fn length(
+    _: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    let length = match &arguments[0] {
+        Value::List(elements) => elements.len(),
+        Value::Str(string) => string.chars().count(),
+        other => {
+            return Err(RuntimeError::InvalidType {
+                found: other.type_name(),
+                operation: "length".to_string(),
+            })
+        }
+    };
+    Ok(Value::SmallInt(length as i64))
+}
+
+/// Threads an accumulator through a two-parameter function, left to right.
+fn fold(
+    context: &mut EvalContext,
+    variables: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    if let Value::Function(function) = &arguments[0] {
+        if function.parameter_names.len() != 2 {
+            return Err(RuntimeError::ParameterMismatch {
+                expected: function.parameter_names.len(),
+                found: 2,
+            });
+        }
+    }
+    let mut accumulator = arguments[1].clone();
+    for element in list_argument(&arguments[2], "fold")? {
+        accumulator = Value::apply(
+            context,
+            variables,
+            &arguments[0],
+            vec![accumulator, element.clone()],
+        )?;
+    }
+    Ok(accumulator)
+}
+
+/// Threads an accumulator through a two-parameter function, right to left, with the element
+/// first: `reduceRight(f, a, [x, y])` is `f(x, f(y, a))`.
+fn reduce_right(
+    context: &mut EvalContext,
+    variables: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    if let Value::Function(function) = &arguments[0] {
+        if function.parameter_names.len() != 2 {
+            return Err(RuntimeError::ParameterMismatch {
+                expected: function.parameter_names.len(),
+                found: 2,
+            });
+        }
+    }
+    let mut accumulator = arguments[1].clone();
+    for element in list_argument(&arguments[2], "reduceRight")?.iter().rev() {
+        accumulator = Value::apply(
+            context,
+            variables,
+            &arguments[0],
+            vec![element.clone(), accumulator],
+        )?;
+    }
+    Ok(accumulator)
+}
+
+/// `range(start, end)` counts up from `start` to just before `end`; `range(start, end, step)`
+/// counts by `step`, which may be negative to count down but can't be zero.
+fn range(
+    context: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    let start = integer_argument(&arguments[0], "range")?;
+    let end = integer_argument(&arguments[1], "range")?;
+    let step = match arguments.get(2) {
+        Some(step) => integer_argument(step, "range")?,
+        None => 1,
+    };
+    if step == 0 {
+        return Err(RuntimeError::InvalidArgument {
+            function: "range".to_string(),
+            reason: "the step can't be zero".to_string(),
+        });
+    }
+    let distance = (end as i128 - start as i128) * step.signum() as i128;
+    let length = if distance > 0 {
+        (distance as u128).div_ceil(step.unsigned_abs() as u128)
+    } else {
+        0
+    };
+    if let Some(limit) = context.max_range_length {
+        if length > limit as u128 {
+            return Err(RuntimeError::RangeTooLong { length, limit });
+        }
+    }
+    Ok(Value::List(
+        std::iter::successors(Some(start), |value| value.checked_add(step))
+            .take(length as usize)
+            .map(Value::SmallInt)
+            .collect(),
+    ))
+}
+
+// The operator sections `(+)`, `(-)`, `(*)`, `(/)` and `(^)`, which do just what the operators do.
+
+fn add_operator(
+    context: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    add(
+        arguments[0].clone(),
+        arguments[1].clone(),
+        context.rounding,
+        context.overflow,
+    )
+}
+
+fn subtract_operator(
+    context: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    subtract(
+        arguments[0].clone(),
+        arguments[1].clone(),
+        context.rounding,
+        context.overflow,
+    )
+}
+
+fn multiply_operator(
+    context: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    multiply(
+        arguments[0].clone(),
+        arguments[1].clone(),
+        context.rounding,
+        context.overflow,
+    )
+}
+
+fn divide_operator(
+    context: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    divide(arguments[0].clone(), arguments[1].clone(), context.rounding)
+}
+
+fn power_operator(
+    context: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    safe_power(
+        &arguments[0],
+        &arguments[1],
+        context.rounding,
+        context.overflow,
+    )
+}
+
+/// Combines the elements of a list with `combine`, starting from `identity`. Only numbers are
+/// accepted, so that `sum` of a list of strings is an error rather than a concatenation.
+fn reduce_numbers(
+    context: &EvalContext,
+    list: &Value,
+    function: &str,
+    identity: i64,
+    combine: fn(Value, Value, Option<Rounding>, OverflowMode) -> Result<Value, RuntimeError>,
+) -> Result<Value, RuntimeError> {
+    let mut result = Value::SmallInt(identity);
+    for element in list_argument(list, function)? {
+        if !matches!(
+            element,
+            Value::SmallInt(_) | Value::Real(_) | Value::Rational(_) | Value::Complex { .. }
+        ) {
+            return Err(RuntimeError::TypeMismatch {
+                first: result.type_name(),
+                last: element.type_name(),
+                operation: function.to_string(),
+            });
+        }
+        result = combine(result, element.clone(), context.rounding, context.overflow)?;
+    }
+    Ok(result)
+}
+
+fn sum(
+    context: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    reduce_numbers(context, &arguments[0], "sum", 0, add)
+}
+
+fn product(
+    context: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    reduce_numbers(context, &arguments[0], "product", 1, multiply)
+}
+
+/// `profile(() => expression)` evaluates the expression by calling the function, counting how
+/// many times each named user function is called along the way. It returns `(name, count)`
+/// tuples, most called first, counting afresh on every call.
+fn profile(
+    context: &mut EvalContext,
+    variables: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    if let Value::Function(function) = &arguments[0] {
+        if !function.parameter_names.is_empty() {
+            return Err(RuntimeError::ParameterMismatch {
+                expected: function.parameter_names.len(),
+                found: 0,
+            });
+        }
+    }
+    let outer = context.call_counts.replace(BTreeMap::new());
+    let result = Value::apply(context, variables, &arguments[0], Vec::new());
+    let counts = std::mem::replace(&mut context.call_counts, outer).unwrap_or_default();
+    // An enclosing profile still sees these calls.
+    if let Some(outer) = &mut context.call_counts {
+        for (name, count) in &counts {
+            *outer.entry(name.clone()).or_insert(0) += count;
+        }
+    }
+    result?;
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|(_, a), (_, b)| b.cmp(a));
+    Ok(Value::List(
+        counts
+            .into_iter()
+            .map(|(name, count)| {
+                Value::Tuple(vec![
+                    Value::Str(name),
+                    Value::SmallInt(count.try_into().unwrap_or(i64::MAX)),
+                ])
+            })
+            .collect(),
+    ))
+}
+
+/// `weightedMean(values, weights)` is the sum of each value times its weight, divided by the sum
+/// of the weights, as a Decimal. Both lists must be real numbers of the same, non-zero length.
+fn weighted_mean(
+    context: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    let invalid = |reason: String| RuntimeError::InvalidArgument {
+        function: "weightedMean".to_string(),
+        reason,
+    };
+    let values = list_argument(&arguments[0], "weightedMean")?;
+    let weights = list_argument(&arguments[1], "weightedMean")?;
+    if values.len() != weights.len() {
+        return Err(invalid(format!(
+            "the lists have lengths {} and {}",
+            values.len(),
+            weights.len()
+        )));
+    }
+    if values.is_empty() {
+        return Err(invalid("the lists are empty".to_string()));
+    }
+    let mut total = Value::SmallInt(0);
+    let mut total_weight = Value::SmallInt(0);
+    for (value, weight) in values.iter().zip(weights) {
+        for element in [value, weight] {
+            if as_real(element).is_none() {
+                return Err(RuntimeError::InvalidType {
+                    found: element.type_name(),
+                    operation: "weightedMean".to_string(),
+                });
+            }
+        }
+        let weighted = multiply(
+            value.clone(),
+            weight.clone(),
+            context.rounding,
+            context.overflow,
+        )?;
+        total = add(total, weighted, context.rounding, context.overflow)?;
+        total_weight = add(
+            total_weight,
+            weight.clone(),
+            context.rounding,
+            context.overflow,
+        )?;
+    }
+    if numeric_ordering(&total_weight, &Value::SmallInt(0), "weightedMean")? == Ordering::Equal {
+        return Err(invalid("the weights add up to zero".to_string()));
+    }
+    let mean = divide(total, total_weight, context.rounding)?;
+    Ok(Value::Real(as_real(&mean).unwrap()))
+}
+
+/// Writes its argument to the context's output on a line of its own, and returns it unchanged,
+/// so that it can be wrapped around any subexpression while debugging.
+fn print(
+    context: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    // A closed or broken output isn't an error in the program being run.
+    let _ = writeln!(context.output, "{}", arguments[0].display(&context.display));
+    Ok(arguments[0].clone())
+}
+
+const BUILTINS: &[NativeFunction] = &[
+    NativeFunction {
+        name: "(*)",
+        description: "(*)(a, b) is a * b.",
+        minimum_arguments: 2,
+        maximum_arguments: Some(2),
+        function: multiply_operator,
+    },
+    NativeFunction {
+        name: "(+)",
+        description: "(+)(a, b) is a + b.",
+        minimum_arguments: 2,
+        maximum_arguments: Some(2),
+        function: add_operator,
+    },
+    NativeFunction {
+        name: "(-)",
+        description: "(-)(a, b) is a - b.",
+        minimum_arguments: 2,
+        maximum_arguments: Some(2),
+        function: subtract_operator,
+    },
+    NativeFunction {
+        name: "(/)",
+        description: "(/)(a, b) is a / b.",
+        minimum_arguments: 2,
+        maximum_arguments: Some(2),
+        function: divide_operator,
+    },
+    NativeFunction {
+        name: "(^)",
+        description: "(^)(a, b) is a ^ b.",
+        minimum_arguments: 2,
+        maximum_arguments: Some(2),
+        function: power_operator,
+    },
+    NativeFunction {
+        name: "abs",
+        description: "The magnitude of a number, or the modulus of a Complex number.",
+        minimum_arguments: 1,
+        maximum_arguments: Some(1),
+        function: abs,
+    },
+    NativeFunction {
+        name: "acos",
+        description: "The arccosine in radians.",
+        minimum_arguments: 1,
+        maximum_arguments: Some(1),
+        function: acos,
+    },
+    NativeFunction {
+        name: "asin",
+        description: "The arcsine in radians.",
+        minimum_arguments: 1,
+        maximum_arguments: Some(1),
+        function: asin,
+    },
+    NativeFunction {
+        name: "assert",
+        description:
+            "assert(condition, message) fails with the optional message unless condition is true.",
+        minimum_arguments: 1,
+        maximum_arguments: Some(2),
+        function: assert,
+    },
+    NativeFunction {
+        name: "assert_eq",
+        description: "assert_eq(actual, expected) fails unless the two values are equal.",
+        minimum_arguments: 2,
+        maximum_arguments: Some(2),
+        function: assert_eq,
+    },
+    NativeFunction {
+        name: "atan",
+        description: "The arctangent in radians.",
+        minimum_arguments: 1,
+        maximum_arguments: Some(1),
+        function: atan,
+    },
+    NativeFunction {
+        name: "atan2",
+        description: "atan2(y, x) is the angle of the point (x, y), between -π and π.",
+        minimum_arguments: 2,
+        maximum_arguments: Some(2),
+        function: atan2,
+    },
+    NativeFunction {
+        name: "cbrt",
+        description: "The real cube root.",
+        minimum_arguments: 1,
+        maximum_arguments: Some(1),
+        function: cbrt,
+    },
+    NativeFunction {
+        name: "clamp",
+        description: "clamp(x, low, high) is the closest value to x between the bounds.",
+        minimum_arguments: 3,
+        maximum_arguments: Some(3),
+        function: clamp,
+    },
+    NativeFunction {
+        name: "cosh",
+        description: "The hyperbolic cosine.",
+        minimum_arguments: 1,
+        maximum_arguments: Some(1),
+        function: cosh,
+    },
+    NativeFunction {
+        name: "divides",
+        description: "divides(a, b) checks that b is a multiple of a.",
+        minimum_arguments: 2,
+        maximum_arguments: Some(2),
+        function: divides,
+    },
+    NativeFunction {
+        name: "filter",
+        description: "filter(f, list) keeps the elements for which f returns true.",
+        minimum_arguments: 2,
+        maximum_arguments: Some(2),
+        function: filter,
+    },
+    NativeFunction {
+        name: "fold",
+        description: "fold(f, initial, list) threads an accumulator through f, left to right.",
+        minimum_arguments: 3,
+        maximum_arguments: Some(3),
+        function: fold,
+    },
+    NativeFunction {
+        name: "gcd",
+        description: "The greatest common divisor of two integers.",
+        minimum_arguments: 2,
+        maximum_arguments: Some(2),
+        function: gcd,
+    },
+    NativeFunction {
+        name: "gcdList",
+        description: "The greatest common divisor of a non-empty list of integers.",
+        minimum_arguments: 1,
+        maximum_arguments: Some(1),
+        function: gcd_list,
+    },
+    NativeFunction {
+        name: "inRange",
+        description: "inRange(x, low, high, exclusive) checks that x lies between the bounds.",
+        minimum_arguments: 3,
+        maximum_arguments: Some(4),
+        function: in_range,
+    },
+    NativeFunction {
+        name: "is_even",
+        description: "Whether an integer is even.",
+        minimum_arguments: 1,
+        maximum_arguments: Some(1),
+        function: is_even,
+    },
+    NativeFunction {
+        name: "is_odd",
+        description: "Whether an integer is odd.",
+        minimum_arguments: 1,
+        maximum_arguments: Some(1),
+        function: is_odd,
+    },
+    NativeFunction {
+        name: "lcm",
+        description: "The least common multiple of two integers.",
+        minimum_arguments: 2,
+        maximum_arguments: Some(2),
+        function: lcm,
+    },
+    NativeFunction {
+        name: "lcmList",
+        description: "The least common multiple of a non-empty list of integers.",
+        minimum_arguments: 1,
+        maximum_arguments: Some(1),
+        function: lcm_list,
+    },
+    NativeFunction {
+        name: "length",
+        description: "The number of elements in a list, or characters in a string.",
+        minimum_arguments: 1,
+        maximum_arguments: Some(1),
+        function: length,
+    },
+    NativeFunction {
+        name: "map",
+        description: "map(f, list) applies f to every element.",
+        minimum_arguments: 2,
+        maximum_arguments: Some(2),
+        function: map,
+    },
+    NativeFunction {
+        name: "max",
+        description: "The largest of its arguments.",
+        minimum_arguments: 2,
+        maximum_arguments: None,
+        function: max,
+    },
+    NativeFunction {
+        name: "memoize",
+        description: "Wraps a function so that it remembers its results.",
+        minimum_arguments: 1,
+        maximum_arguments: Some(1),
+        function: memoize,
+    },
+    NativeFunction {
+        name: "min",
+        description: "The smallest of its arguments.",
+        minimum_arguments: 2,
+        maximum_arguments: None,
+        function: min,
+    },
+    NativeFunction {
+        name: "print",
+        description: "Prints its argument and returns it unchanged.",
+        minimum_arguments: 1,
+        maximum_arguments: Some(1),
+        function: print,
+    },
+    NativeFunction {
+        name: "product",
+        description: "Multiplies the numbers in a list; the product of an empty list is 1.",
+        minimum_arguments: 1,
+        maximum_arguments: Some(1),
+        function: product,
+    },
+    NativeFunction {
+        name: "profile",
+        description: "profile(() => expression) counts the calls to each function while evaluating the expression.",
+        minimum_arguments: 1,
+        maximum_arguments: Some(1),
+        function: profile,
+    },
+    NativeFunction {
+        name: "range",
+        description: "range(start, end) counts from start up to, but not including, end; a third argument sets the step.",
+        minimum_arguments: 2,
+        maximum_arguments: Some(3),
+        function: range,
+    },
+    NativeFunction {
+        name: "reduceRight",
+        description: "reduceRight(f, initial, list) threads an accumulator through f(x, acc), right to left.",
+        minimum_arguments: 3,
+        maximum_arguments: Some(3),
+        function: reduce_right,
+    },
+    NativeFunction {
+        name: "root",
+        description: "root(n, x) is the real nth root of x.",
+        minimum_arguments: 2,
+        maximum_arguments: Some(2),
+        function: root,
+    },
+    NativeFunction {
+        name: "round_to",
+        description: "round_to(x, digits) rounds x to that many decimal places, at most 1000.",
+        minimum_arguments: 2,
+        maximum_arguments: Some(2),
+        function: round_to,
+    },
+    NativeFunction {
+        name: "sinh",
+        description: "The hyperbolic sine.",
+        minimum_arguments: 1,
+        maximum_arguments: Some(1),
+        function: sinh,
+    },
+    NativeFunction {
+        name: "sqrt",
+        description: "The principal square root, which is Complex for negative numbers.",
+        minimum_arguments: 1,
+        maximum_arguments: Some(1),
+        function: sqrt,
+    },
+    NativeFunction {
+        name: "sum",
+        description: "Adds up the numbers in a list; the sum of an empty list is 0.",
+        minimum_arguments: 1,
+        maximum_arguments: Some(1),
+        function: sum,
+    },
+    NativeFunction {
+        name: "tanh",
+        description: "The hyperbolic tangent.",
+        minimum_arguments: 1,
+        maximum_arguments: Some(1),
+        function: tanh,
+    },
+    NativeFunction {
+        name: "type",
+        description: "The name of the type of any value.",
+        minimum_arguments: 1,
+        maximum_arguments: Some(1),
+        function: type_of,
+    },
+    NativeFunction {
+        name: "weightedMean",
+        description: "weightedMean(values, weights) is the mean of values, weighted by weights.",
+        minimum_arguments: 2,
+        maximum_arguments: Some(2),
+        function: weighted_mean,
+    },
+];
+
+/// The constants every program starts with, along with what they are.
+const CONSTANTS: &[(&str, &str)] = &[("i", "The imaginary unit.")];
+
+/// A line of help for each builtin and constant, in order of name: its name, how many
+/// arguments it takes, and what it does.
+pub fn help() -> Vec<(&'static str, String, &'static str)> {
+    let mut entries: Vec<_> = BUILTINS
+        .iter()
+        .map(|builtin| {
+            let arguments = match (builtin.minimum_arguments, builtin.maximum_arguments) {
+                (1, Some(1)) => "1 argument".to_string(),
+                (minimum, Some(maximum)) if minimum == maximum => format!("{} arguments", minimum),
+                (minimum, Some(maximum)) => format!("{} to {} arguments", minimum, maximum),
+                (minimum, None) => format!("{} or more arguments", minimum),
+            };
+            (builtin.name, arguments, builtin.description)
+        })
+        .chain(
+            CONSTANTS
+                .iter()
+                .map(|&(name, description)| (name, "constant".to_string(), description)),
+        )
+        .collect();
+    entries.sort_by_key(|&(name, _, _)| name);
+    entries
+}
+
+/// The bindings every program starts with.
+pub fn environment() -> BTreeMap<String, Value> {
+    let mut environment: BTreeMap<String, Value> = BUILTINS
+        .iter()
+        .map(|builtin| {
+            (
+                builtin.name.to_string(),
+                Value::NativeFunction(builtin.clone()),
+            )
+        })
+        .collect();
+    environment.insert(
+        "i".to_string(),
+        Value::Complex {
+            re: create_real(0),
+            im: create_real(1),
+        },
+    );
+    environment
+}
+
+/// Whether `name` is bound by [`environment`], so that defining it would shadow a builtin.
+pub fn is_builtin(name: &str) -> bool {
+    BUILTINS.iter().any(|builtin| builtin.name == name)
+        || CONSTANTS.iter().any(|(constant, _)| *constant == name)
+}
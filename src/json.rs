@@ -0,0 +1,346 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::parser::{Expression, Pattern, Span, TopLevelStatement, Type};
+
+/// A JSON document, used to dump the syntax tree for external tools.
+#[derive(Debug, Clone)]
+pub enum Json {
+    Bool(bool),
+    Number(i64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+fn node(kind: &str, fields: Vec<(&str, Json)>) -> Json {
+    let mut object = vec![("kind".to_string(), Json::String(kind.to_string()))];
+    object.extend(
+        fields
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value)),
+    );
+    Json::Object(object)
+}
+
+fn unary(kind: &str, operand: &Expression) -> Json {
+    node(kind, vec![("operand", operand.into())])
+}
+
+fn binary(kind: &str, lhs: &Expression, rhs: &Expression) -> Json {
+    node(kind, vec![("lhs", lhs.into()), ("rhs", rhs.into())])
+}
+
+impl From<&Span> for Json {
+    fn from(span: &Span) -> Json {
+        Json::Object(
+            [
+                ("start", span.start),
+                ("end", span.end),
+                ("line", span.line),
+                ("column", span.column),
+            ]
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), Json::Number(value as i64)))
+            .collect(),
+        )
+    }
+}
+
+fn binding_list(bindings: &[(String, Expression)]) -> Json {
+    Json::Array(
+        bindings
+            .iter()
+            .map(|(name, value)| {
+                node(
+                    "Binding",
+                    vec![
+                        ("name", Json::String(name.clone())),
+                        ("value", value.into()),
+                    ],
+                )
+            })
+            .collect(),
+    )
+}
+
+fn names(names: &[String]) -> Json {
+    Json::Array(names.iter().cloned().map(Json::String).collect())
+}
+
+impl From<&Type> for Json {
+    fn from(typ: &Type) -> Json {
+        match typ {
+            Type::Named(name) => node("Named", vec![("name", Json::String(name.clone()))]),
+            Type::Product(types) => node(
+                "Product",
+                vec![("types", Json::Array(types.iter().map(Json::from).collect()))],
+            ),
+        }
+    }
+}
+
+impl From<&Pattern> for Json {
+    fn from(pattern: &Pattern) -> Json {
+        match pattern {
+            Pattern::Integer(value) => node("Integer", vec![("value", Json::Number(*value))]),
+            Pattern::Wildcard => node("Wildcard", Vec::new()),
+        }
+    }
+}
+
+impl From<&Expression> for Json {
+    fn from(expression: &Expression) -> Json {
+        match expression {
+            Expression::Integer(value) => node("Integer", vec![("value", Json::Number(*value))]),
+            Expression::Real(value) => {
+                node("Real", vec![("value", Json::String(value.to_string()))])
+            }
+            Expression::Bool(value) => node("Bool", vec![("value", Json::Bool(*value))]),
+            Expression::Str(value) => node("Str", vec![("value", Json::String(value.clone()))]),
+            Expression::Variable { name, span } => {
+                let mut fields = vec![("name", Json::String(name.clone()))];
+                fields.extend(span.as_ref().map(|span| ("span", span.into())));
+                node("Variable", fields)
+            }
+            Expression::Negate(a) => unary("Negate", a),
+            Expression::Abs(a) => unary("Abs", a),
+            Expression::Add(a, b) => binary("Add", a, b),
+            Expression::Subtract(a, b) => binary("Subtract", a, b),
+            Expression::Multiply(a, b) => binary("Multiply", a, b),
+            Expression::Divide(a, b) => binary("Divide", a, b),
+            Expression::IntegerDivide(a, b) => binary("IntegerDivide", a, b),
+            Expression::Power(a, b) => binary("Power", a, b),
+            Expression::Factorial(a) => unary("Factorial", a),
+            Expression::Equal(a, b) => binary("Equal", a, b),
+            Expression::NotEqual(a, b) => binary("NotEqual", a, b),
+            Expression::LessThan(a, b) => binary("LessThan", a, b),
+            Expression::LessThanOrEqual(a, b) => binary("LessThanOrEqual", a, b),
+            Expression::GreaterThan(a, b) => binary("GreaterThan", a, b),
+            Expression::GreaterThanOrEqual(a, b) => binary("GreaterThanOrEqual", a, b),
+            Expression::BitAnd(a, b) => binary("BitAnd", a, b),
+            Expression::BitOr(a, b) => binary("BitOr", a, b),
+            Expression::BitXor(a, b) => binary("BitXor", a, b),
+            Expression::ShiftLeft(a, b) => binary("ShiftLeft", a, b),
+            Expression::ShiftRight(a, b) => binary("ShiftRight", a, b),
+            Expression::And(a, b) => binary("And", a, b),
+            Expression::If {
+                condition,
+                then,
+                otherwise,
+            } => node(
+                "If",
+                vec![
+                    ("condition", (&**condition).into()),
+                    ("then", (&**then).into()),
+                    ("otherwise", (&**otherwise).into()),
+                ],
+            ),
+            Expression::Guarded {
+                branches,
+                otherwise,
+            } => {
+                let mut fields = vec![(
+                    "branches",
+                    Json::Array(
+                        branches
+                            .iter()
+                            .map(|(condition, result)| {
+                                node(
+                                    "Branch",
+                                    vec![
+                                        ("condition", condition.into()),
+                                        ("result", result.into()),
+                                    ],
+                                )
+                            })
+                            .collect(),
+                    ),
+                )];
+                fields.extend(
+                    otherwise
+                        .as_ref()
+                        .map(|otherwise| ("otherwise", (&**otherwise).into())),
+                );
+                node("Guarded", fields)
+            }
+            Expression::Match { scrutinee, arms } => node(
+                "Match",
+                vec![
+                    ("scrutinee", (&**scrutinee).into()),
+                    (
+                        "arms",
+                        Json::Array(
+                            arms.iter()
+                                .map(|(pattern, result)| {
+                                    node(
+                                        "Arm",
+                                        vec![
+                                            ("pattern", pattern.into()),
+                                            ("result", result.into()),
+                                        ],
+                                    )
+                                })
+                                .collect(),
+                        ),
+                    ),
+                ],
+            ),
+            Expression::List(elements) => node(
+                "List",
+                vec![(
+                    "elements",
+                    Json::Array(elements.iter().map(Json::from).collect()),
+                )],
+            ),
+            Expression::Tuple(elements) => node(
+                "Tuple",
+                vec![(
+                    "elements",
+                    Json::Array(elements.iter().map(Json::from).collect()),
+                )],
+            ),
+            Expression::Index { list, index } => node(
+                "Index",
+                vec![("list", (&**list).into()), ("index", (&**index).into())],
+            ),
+            Expression::ApplyFunction {
+                function,
+                arguments,
+            } => node(
+                "ApplyFunction",
+                vec![
+                    ("function", (&**function).into()),
+                    (
+                        "arguments",
+                        Json::Array(arguments.iter().map(Json::from).collect()),
+                    ),
+                ],
+            ),
+            Expression::Block { bindings, result } => node(
+                "Block",
+                vec![
+                    ("bindings", binding_list(bindings)),
+                    ("result", (&**result).into()),
+                ],
+            ),
+            Expression::Lambda {
+                parameters,
+                guards,
+                body,
+            } => node(
+                "Lambda",
+                vec![
+                    ("parameters", names(parameters)),
+                    (
+                        "guards",
+                        Json::Array(
+                            guards
+                                .iter()
+                                .map(|(parameter, guard)| {
+                                    node(
+                                        "Guard",
+                                        vec![
+                                            ("parameter", Json::String(parameter.clone())),
+                                            ("condition", guard.into()),
+                                        ],
+                                    )
+                                })
+                                .collect(),
+                        ),
+                    ),
+                    ("body", (&**body).into()),
+                ],
+            ),
+        }
+    }
+}
+
+impl From<&TopLevelStatement> for Json {
+    fn from(statement: &TopLevelStatement) -> Json {
+        match statement {
+            TopLevelStatement::FunctionTypeDeclaration {
+                name,
+                domain,
+                codomain,
+            } => node(
+                "FunctionTypeDeclaration",
+                vec![
+                    ("name", Json::String(name.clone())),
+                    ("domain", domain.into()),
+                    ("codomain", codomain.into()),
+                ],
+            ),
+            TopLevelStatement::FunctionDefinition {
+                name,
+                parameters,
+                body,
+                bindings,
+            } => node(
+                "FunctionDefinition",
+                vec![
+                    ("name", Json::String(name.clone())),
+                    ("parameters", names(parameters)),
+                    ("body", body.into()),
+                    ("bindings", binding_list(bindings)),
+                ],
+            ),
+            TopLevelStatement::VariableDefinition { name, value } => node(
+                "VariableDefinition",
+                vec![
+                    ("name", Json::String(name.clone())),
+                    ("value", value.into()),
+                ],
+            ),
+            TopLevelStatement::Expression(value) => {
+                node("Expression", vec![("value", value.into())])
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut Formatter, string: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in string.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Number(value) => write!(f, "{}", value),
+            Json::String(value) => write_string(f, value),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (index, (name, value)) in fields.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, name)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
@@ -0,0 +1,473 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Display, Formatter},
+};
+
+use parser::{
+    parse_complete_expression, parse_program, parse_program_with_errors, Expression, Span,
+    Statements, SyntaxError, TopLevelStatement, Type, IGNORED_PARAMETER,
+};
+use value::{EvalContext, Function, NativeFunction, RuntimeError, Value};
+
+pub mod builtins;
+pub mod json;
+pub mod parser;
+pub mod value;
+
+/// Anything that can go wrong when running source through an [`Interpreter`].
+#[derive(Debug, Clone)]
+pub enum AlephError {
+    ParseError {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    Runtime(RuntimeError),
+}
+
+impl From<SyntaxError> for AlephError {
+    fn from(error: SyntaxError) -> Self {
+        AlephError::ParseError {
+            line: error.line,
+            column: error.column,
+            message: error.message,
+        }
+    }
+}
+
+impl From<RuntimeError> for AlephError {
+    fn from(error: RuntimeError) -> Self {
+        AlephError::Runtime(error)
+    }
+}
+
+impl Display for AlephError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            AlephError::ParseError {
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "parse error at line {}, column {}: {}",
+                line, column, message
+            ),
+            AlephError::Runtime(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for AlephError {}
+
+/// A problem found by [`Interpreter::check`], located by line and column (both starting at 1).
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn at(span: Span, message: String) -> Diagnostic {
+        Diagnostic {
+            line: span.line,
+            column: span.column,
+            message,
+        }
+    }
+}
+
+impl From<SyntaxError> for Diagnostic {
+    fn from(error: SyntaxError) -> Self {
+        Diagnostic {
+            line: error.line,
+            column: error.column,
+            message: format!("parse error: {}", error.message),
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+/// An interpreter that other programs can embed: it holds the bindings made by definitions
+/// and evaluates expressions against them.
+///
+/// User functions can nest up to [`value::DEFAULT_RECURSION_LIMIT`] calls deep unless
+/// `context.recursion_limit` says otherwise. Evaluation moves onto a heap-allocated stack when
+/// the caller's runs low, so it works from any thread, whatever its stack size.
+#[derive(Debug)]
+pub struct Interpreter {
+    pub context: EvalContext,
+    /// Every name in scope, starting with the builtins.
+    pub variables: BTreeMap<String, Value>,
+    /// The parameter types and return type from each function's type declaration.
+    pub declarations: BTreeMap<String, (Vec<Type>, Type)>,
+}
+
+/// The types of a function's parameters, one per parameter for a product domain.
+fn parameter_types(domain: Type) -> Vec<Type> {
+    match domain {
+        Type::Product(types) => types,
+        domain => vec![domain],
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Interpreter {
+            context: EvalContext::default(),
+            variables: builtins::environment(),
+            declarations: BTreeMap::new(),
+        }
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        Interpreter::default()
+    }
+
+    /// Parses and defines every statement in `source`, stopping at the first that fails.
+    /// Nothing is defined if any of its variables are defined in terms of each other.
+    pub fn define(&mut self, source: &str) -> Result<(), AlephError> {
+        let statements = parse_program(source)?;
+        if let Some(names) = self.definition_cycles(&statements).into_iter().next() {
+            return Err(RuntimeError::CyclicDefinition { names }.into());
+        }
+        for statement in statements {
+            self.define_statement(statement)?;
+        }
+        Ok(())
+    }
+
+    /// Binds a single top-level statement; variable definitions are evaluated immediately, and
+    /// can only refer to themselves to replace an existing value, as in `x = x + 1`.
+    /// A type declaration applies to its function whether it comes before or after it.
+    pub fn define_statement(&mut self, statement: TopLevelStatement) -> Result<(), RuntimeError> {
+        match statement {
+            TopLevelStatement::FunctionDefinition {
+                name,
+                parameters,
+                body,
+                bindings,
+            } => {
+                // Only the last of two parameters with the same name could ever be seen.
+                for (index, parameter) in parameters.iter().enumerate() {
+                    if parameter != IGNORED_PARAMETER && parameters[..index].contains(parameter) {
+                        return Err(RuntimeError::DuplicateParameter {
+                            function: name,
+                            parameter: parameter.clone(),
+                        });
+                    }
+                }
+                let (parameter_types, return_type) = match self.declarations.get(&name) {
+                    Some((parameter_types, codomain)) => {
+                        (parameter_types.clone(), Some(codomain.clone()))
+                    }
+                    None => (Vec::new(), None),
+                };
+                self.variables.insert(
+                    name.clone(),
+                    Value::Function(Function {
+                        name: Some(name),
+                        parameter_names: parameters,
+                        guards: Vec::new(),
+                        parameter_types,
+                        return_type,
+                        body,
+                        bindings,
+                        captured: BTreeMap::new(),
+                        supplied: 0,
+                        memo_table: None,
+                    }),
+                );
+            }
+            TopLevelStatement::VariableDefinition { name, value } => {
+                if !self.variables.contains_key(&name) && value.free_variables().contains(&name) {
+                    return Err(RuntimeError::CyclicDefinition { names: vec![name] });
+                }
+                let value = self.evaluate(&value)?;
+                self.variables.insert(name, value);
+            }
+            TopLevelStatement::FunctionTypeDeclaration {
+                name,
+                domain,
+                codomain,
+            } => {
+                let types = parameter_types(domain);
+                if let Some(Value::Function(function)) = self.variables.get_mut(&name) {
+                    function.parameter_types = types.clone();
+                    function.return_type = Some(codomain.clone());
+                }
+                self.declarations.insert(name, (types, codomain));
+            }
+            TopLevelStatement::Expression(expression) => {
+                self.evaluate(&expression)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses and runs the statements in `source` one at a time, as the iterator is advanced:
+    /// definitions are defined and bare expressions evaluated. Each statement yields itself
+    /// along with the value it bound or evaluated to, if any, or the error that stopped it.
+    /// A statement that fails to parse yields the syntax error, which gives its line, and
+    /// parsing resumes on the next line; later statements still run either way.
+    pub fn define_incrementally<'a>(
+        &'a mut self,
+        source: &'a str,
+    ) -> impl Iterator<
+        Item = Result<(TopLevelStatement, Result<Option<Value>, AlephError>), SyntaxError>,
+    > + 'a {
+        Statements::with_expressions(source).map(move |parsed| {
+            let statement = parsed?;
+            let outcome = self.run_statement(statement.clone());
+            Ok((statement, outcome))
+        })
+    }
+
+    fn run_statement(&mut self, statement: TopLevelStatement) -> Result<Option<Value>, AlephError> {
+        match statement {
+            TopLevelStatement::Expression(expression) => Ok(Some(self.evaluate(&expression)?)),
+            TopLevelStatement::VariableDefinition { ref name, .. } => {
+                let name = name.clone();
+                self.define_statement(statement)?;
+                Ok(self.variables.get(&name).cloned())
+            }
+            _ => {
+                self.define_statement(statement)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Parses and evaluates a single expression.
+    pub fn eval(&mut self, source: &str) -> Result<Value, AlephError> {
+        let expression = parse_complete_expression(source)?;
+        Ok(self.evaluate(&expression)?)
+    }
+
+    pub fn evaluate(&mut self, expression: &Expression) -> Result<Value, RuntimeError> {
+        Value::evaluate(&mut self.context, &self.variables, expression)
+    }
+
+    /// Binds a function implemented in Rust under its name, replacing anything bound to it.
+    pub fn register(&mut self, function: NativeFunction) {
+        self.variables
+            .insert(function.name.to_string(), Value::NativeFunction(function));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.variables.get(name)
+    }
+
+    /// Finds names used in function bodies that are neither parameters nor defined by
+    /// `statements` or already bound, as `(function, variable, where it is first used)`.
+    pub fn unbound_variables(
+        &self,
+        statements: &[TopLevelStatement],
+    ) -> Vec<(String, String, Option<Span>)> {
+        let defined: BTreeSet<&str> = statements
+            .iter()
+            .filter_map(|statement| match statement {
+                TopLevelStatement::FunctionDefinition { name, .. }
+                | TopLevelStatement::VariableDefinition { name, .. } => Some(name.as_str()),
+                TopLevelStatement::FunctionTypeDeclaration { .. }
+                | TopLevelStatement::Expression(_) => None,
+            })
+            .collect();
+        let mut unbound = Vec::new();
+        for statement in statements {
+            if let TopLevelStatement::FunctionDefinition {
+                name,
+                parameters,
+                body,
+                bindings,
+            } = statement
+            {
+                // A `where` clause scopes its bindings over the body just like a block.
+                let scoped = Expression::Block {
+                    bindings: bindings.clone(),
+                    result: Box::new(body.clone()),
+                };
+                for (variable, span) in scoped.free_variable_spans() {
+                    if !parameters.contains(&variable)
+                        && !defined.contains(variable.as_str())
+                        && !self.variables.contains_key(&variable)
+                    {
+                        unbound.push((name.clone(), variable, span));
+                    }
+                }
+            }
+        }
+        unbound
+    }
+
+    /// Finds the variables defined by `statements` in terms of themselves, either directly or
+    /// through other variables defined there, leaving out names that are already bound. Each
+    /// group of variables that depend on each other is listed once, in the order defined.
+    pub fn definition_cycles(&self, statements: &[TopLevelStatement]) -> Vec<Vec<String>> {
+        let mut dependencies: BTreeMap<&str, BTreeSet<String>> = BTreeMap::new();
+        let mut order = Vec::new();
+        for statement in statements {
+            if let TopLevelStatement::VariableDefinition { name, value } = statement {
+                if !self.variables.contains_key(name) && !dependencies.contains_key(name.as_str()) {
+                    dependencies.insert(name, value.free_variables());
+                    order.push(name.as_str());
+                }
+            }
+        }
+        let reachable = |start: &str| {
+            let mut seen = BTreeSet::new();
+            let mut pending: Vec<&str> = vec![start];
+            while let Some(name) = pending.pop() {
+                for next in dependencies.get(name).into_iter().flatten() {
+                    if dependencies.contains_key(next.as_str()) && seen.insert(next.clone()) {
+                        pending.push(next);
+                    }
+                }
+            }
+            seen
+        };
+        let reachable: BTreeMap<&str, BTreeSet<String>> =
+            order.iter().map(|&name| (name, reachable(name))).collect();
+        let mut cycles: Vec<Vec<String>> = Vec::new();
+        for &name in &order {
+            if !reachable[name].contains(name)
+                || cycles
+                    .iter()
+                    .any(|cycle| cycle.iter().any(|member| member == name))
+            {
+                continue;
+            }
+            cycles.push(
+                order
+                    .iter()
+                    .filter(|&&other| {
+                        reachable[name].contains(other) && reachable[other].contains(name)
+                    })
+                    .map(|other| other.to_string())
+                    .collect(),
+            );
+        }
+        cycles
+    }
+
+    /// Checks a program without evaluating any of it, reporting every syntax error, every
+    /// unbound variable in a function body and every call with the wrong number of arguments
+    /// to a function it knows.
+    pub fn check(&self, source: &str) -> Vec<Diagnostic> {
+        let (statements, errors) = parse_program_with_errors(source);
+        let mut diagnostics: Vec<Diagnostic> = errors.into_iter().map(Diagnostic::from).collect();
+        for (function, variable, span) in self.unbound_variables(&statements) {
+            // Only names the parser makes up lack a span, and those are always bound.
+            if let Some(span) = span {
+                let message = format!("{} refers to unbound variable {}", function, variable);
+                diagnostics.push(Diagnostic::at(span, message));
+            }
+        }
+        for statement in &statements {
+            match statement {
+                TopLevelStatement::FunctionDefinition {
+                    parameters,
+                    body,
+                    bindings,
+                    ..
+                } => {
+                    // Calls through local bindings can't be checked any more than parameters.
+                    let locals: Vec<String> = parameters
+                        .iter()
+                        .chain(bindings.iter().map(|(name, _)| name))
+                        .cloned()
+                        .collect();
+                    for (_, value) in bindings {
+                        self.check_calls(&statements, &locals, value, &mut diagnostics);
+                    }
+                    self.check_calls(&statements, &locals, body, &mut diagnostics)
+                }
+                TopLevelStatement::VariableDefinition { value, .. }
+                | TopLevelStatement::Expression(value) => {
+                    self.check_calls(&statements, &[], value, &mut diagnostics)
+                }
+                TopLevelStatement::FunctionTypeDeclaration { .. } => {}
+            }
+        }
+        diagnostics.sort_by_key(|diagnostic| (diagnostic.line, diagnostic.column));
+        diagnostics
+    }
+
+    /// Reports calls in `expression` to named functions that take fewer arguments than given,
+    /// or (for builtins) more than given. Calls through `parameters` can't be checked.
+    fn check_calls(
+        &self,
+        statements: &[TopLevelStatement],
+        parameters: &[String],
+        expression: &Expression,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        if let Expression::ApplyFunction {
+            function,
+            arguments,
+        } = expression
+        {
+            if let Expression::Variable {
+                name,
+                span: Some(span),
+            } = &**function
+            {
+                let found = arguments.len();
+                let expected = match self.arity(statements, name) {
+                    _ if parameters.contains(name) => None,
+                    Some((_, Some(maximum))) if found > maximum => Some(maximum),
+                    Some((minimum, _)) if found < minimum => Some(minimum),
+                    _ => None,
+                };
+                if let Some(expected) = expected {
+                    let error = RuntimeError::ParameterMismatch { expected, found };
+                    diagnostics.push(Diagnostic::at(*span, format!("{}: {}", name, error)));
+                }
+            }
+        }
+        for subexpression in expression.subexpressions() {
+            self.check_calls(statements, parameters, subexpression, diagnostics);
+        }
+    }
+
+    /// The fewest and most arguments the function called `name` accepts when called by that
+    /// name, preferring a definition in `statements`. User functions can be partially applied.
+    fn arity(
+        &self,
+        statements: &[TopLevelStatement],
+        name: &str,
+    ) -> Option<(usize, Option<usize>)> {
+        for statement in statements.iter().rev() {
+            match statement {
+                TopLevelStatement::FunctionDefinition {
+                    name: defined,
+                    parameters,
+                    ..
+                } if defined == name => return Some((0, Some(parameters.len()))),
+                TopLevelStatement::VariableDefinition { name: defined, .. } if defined == name => {
+                    return None
+                }
+                _ => {}
+            }
+        }
+        match self.variables.get(name)? {
+            Value::Function(function) => Some((0, Some(function.parameter_names.len()))),
+            Value::NativeFunction(native) => {
+                Some((native.minimum_arguments, native.maximum_arguments))
+            }
+            _ => None,
+        }
+    }
+}
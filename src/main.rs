@@ -1,44 +1,756 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, path::PathBuf};
 
-use parser::{parse_expression, parse_top_level, TopLevelStatement};
-use value::Value;
+use rustyline::{
+    error::ReadlineError,
+    history::{DefaultHistory, History},
+    Config, DefaultEditor,
+};
 
-mod parser;
-mod value;
+use aleph_language::{
+    builtins,
+    json::Json,
+    parser::{
+        is_name_char, parse_complete_definition, parse_complete_expression, parse_program,
+        Expression, TopLevelStatement, IGNORED_PARAMETER,
+    },
+    value::{
+        locate_failure, DisplaySettings, OverflowMode, Rounding, RuntimeError, ScientificRange,
+        Value, DEFAULT_MAX_RANGE_LENGTH, DEFAULT_RECURSION_LIMIT,
+    },
+    Interpreter,
+};
 
-fn main() {
-    let mut variables = BTreeMap::new();
-    let args: Vec<String> = std::env::args().collect();
-    for file_name in &args[1..] {
-        let input = std::fs::read_to_string(file_name).unwrap();
-        let (_, top_level) = parse_top_level(&input).unwrap();
-        println!("{:?}", top_level);
-        for item in top_level {
-            if let TopLevelStatement::FunctionDefinition {
-                name,
-                parameters,
-                body,
-            } = item
-            {
-                variables.insert(
-                    name.clone(),
-                    Value::Function(value::Function {
-                        name,
-                        parameter_names: parameters,
-                        body,
-                    }),
+fn warn_unbound_variables(interpreter: &Interpreter, statements: &[TopLevelStatement]) {
+    for (function, variable, span) in interpreter.unbound_variables(statements) {
+        match span {
+            Some(span) => eprintln!(
+                "warning: {} refers to unbound variable {} at {}",
+                function, variable, span
+            ),
+            None => eprintln!(
+                "warning: {} refers to unbound variable {}",
+                function, variable
+            ),
+        }
+    }
+}
+
+/// Defines a statement, warning if it replaces a builtin, a function's parameter hides a global
+/// or a `match` has an arm that can never be chosen; the definition still takes effect.
+/// Returns whether the definition succeeded.
+fn define(interpreter: &mut Interpreter, statement: TopLevelStatement) -> bool {
+    if let TopLevelStatement::FunctionDefinition { name, .. }
+    | TopLevelStatement::VariableDefinition { name, .. } = &statement
+    {
+        if builtins::is_builtin(name) {
+            eprintln!("warning: definition shadows the builtin {}", name);
+        }
+    }
+    if let TopLevelStatement::FunctionDefinition {
+        name, parameters, ..
+    } = &statement
+    {
+        for parameter in parameters {
+            let global = interpreter.variables.contains_key(parameter)
+                && !builtins::is_builtin(parameter)
+                && parameter != IGNORED_PARAMETER;
+            if global {
+                eprintln!(
+                    "warning: parameter {} of {} shadows the global {}",
+                    parameter, name, parameter
                 );
             }
         }
     }
+    let (name, expressions) = match &statement {
+        TopLevelStatement::FunctionDefinition {
+            name,
+            body,
+            bindings,
+            ..
+        } => (
+            name,
+            std::iter::once(body)
+                .chain(bindings.iter().map(|(_, value)| value))
+                .collect(),
+        ),
+        TopLevelStatement::VariableDefinition { name, value } => (name, vec![value]),
+        TopLevelStatement::FunctionTypeDeclaration { name, .. } => (name, Vec::new()),
+        TopLevelStatement::Expression(_) => unreachable!("programs are parsed as definitions"),
+    };
+    for expression in expressions {
+        for (pattern, reason) in expression.unreachable_arms() {
+            eprintln!(
+                "warning: match arm {} in {} can never be chosen: {}",
+                pattern, name, reason
+            );
+        }
+    }
+    match interpreter.define_statement(statement) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            false
+        }
+    }
+}
+
+/// Reports variables defined in terms of each other and drops their definitions, which could
+/// only fail.
+fn reject_cycles(interpreter: &Interpreter, statements: &mut Vec<TopLevelStatement>) {
+    let cycles = interpreter.definition_cycles(statements);
+    for names in &cycles {
+        let error = RuntimeError::CyclicDefinition {
+            names: names.clone(),
+        };
+        eprintln!("error: {}", error);
+    }
+    statements.retain(|statement| match statement {
+        TopLevelStatement::VariableDefinition { name, .. } => {
+            !cycles.iter().flatten().any(|member| member == name)
+        }
+        _ => true,
+    });
+}
+
+/// Binds `$1`, `$2` and so on to the command line arguments after `-e`, and `$#` to how many
+/// there are. An argument written as a number is that number; anything else is a string.
+fn bind_positional(interpreter: &mut Interpreter, arguments: &[String]) {
+    for (index, argument) in arguments.iter().enumerate() {
+        let number = match parse_complete_expression(argument) {
+            Ok(expression @ (Expression::Integer(_) | Expression::Real(_))) => Some(expression),
+            Ok(Expression::Negate(operand))
+                if matches!(*operand, Expression::Integer(_) | Expression::Real(_)) =>
+            {
+                Some(Expression::Negate(operand))
+            }
+            _ => None,
+        };
+        let value = number
+            .and_then(|number| interpreter.evaluate(&number).ok())
+            .unwrap_or_else(|| Value::Str(argument.clone()));
+        interpreter
+            .variables
+            .insert(format!("${}", index + 1), value);
+    }
+    interpreter
+        .variables
+        .insert("$#".to_string(), Value::SmallInt(arguments.len() as i64));
+}
+
+fn parse_file(file_name: &str) -> Result<Vec<TopLevelStatement>, String> {
+    let input = std::fs::read_to_string(file_name).map_err(|e| format!("{}: {}", file_name, e))?;
+    parse_program(&input).map_err(|e| format!("{}: {}", file_name, e))
+}
+
+fn load(interpreter: &mut Interpreter, file_name: &str) {
+    match parse_file(file_name) {
+        Ok(mut top_level) => {
+            reject_cycles(interpreter, &mut top_level);
+            warn_unbound_variables(interpreter, &top_level);
+            let mut count = 0;
+            for item in top_level {
+                if !matches!(item, TopLevelStatement::FunctionTypeDeclaration { .. }) {
+                    count += 1;
+                }
+                define(interpreter, item);
+            }
+            println!("loaded {} definitions from {}", count, file_name);
+        }
+        Err(e) => eprintln!("error: {}", e),
+    }
+}
+
+fn profile(interpreter: &mut Interpreter, input: &str) {
+    let expression = match parse_complete_expression(input) {
+        Ok(expression) => expression,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return;
+        }
+    };
+    interpreter.context.call_counts = Some(BTreeMap::new());
+    let result = interpreter.evaluate(&expression);
+    let mut call_counts: Vec<_> = interpreter
+        .context
+        .call_counts
+        .take()
+        .unwrap()
+        .into_iter()
+        .collect();
+    call_counts.sort_by(|(_, a), (_, b)| b.cmp(a));
+    match result {
+        Ok(value) => println!("{}", value.display(&interpreter.context.display)),
+        Err(e) => eprintln!("error: {}", e),
+    }
+    for (name, count) in call_counts {
+        println!("{}: {}", name, count);
+    }
+}
+
+/// Evaluates `input`, and if it fails, shows the innermost subexpression that failed along with
+/// the types of its operands. Returns whether evaluation succeeded.
+fn explain(interpreter: &mut Interpreter, input: &str) -> bool {
+    let expression = match parse_complete_expression(input) {
+        Ok(expression) => expression,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return false;
+        }
+    };
+    match locate_failure(
+        &mut interpreter.context,
+        &interpreter.variables,
+        &expression,
+    ) {
+        Some((culprit, error)) => {
+            println!("{}", culprit);
+            for operand in culprit.operands() {
+                if let Ok(value) = interpreter.evaluate(operand) {
+                    println!("  {} : {}", operand, value.type_name());
+                }
+            }
+            eprintln!("error: {}", error);
+            false
+        }
+        None => match interpreter.evaluate(&expression) {
+            Ok(value) => {
+                println!("{}", value.display(&interpreter.context.display));
+                true
+            }
+            Err(e) => {
+                eprintln!("error: {}", e);
+                false
+            }
+        },
+    }
+}
+
+/// Lists every builtin and constant, or just the one named `topic`.
+fn help(topic: &str) {
+    let entries: Vec<_> = builtins::help()
+        .into_iter()
+        .filter(|(name, _, _)| topic.is_empty() || *name == topic)
+        .collect();
+    if entries.is_empty() {
+        eprintln!("error: no builtin named {}", topic);
+    }
+    for (name, arguments, description) in entries {
+        println!("{} ({}): {}", name, arguments, description);
+    }
+}
+
+fn list_variables(interpreter: &Interpreter) {
+    for (name, value) in &interpreter.variables {
+        match value {
+            Value::Function(function) => {
+                println!("{}({})", name, function.parameter_names.join(", "))
+            }
+            Value::NativeFunction(_) => {}
+            value => println!("{} = {}", name, value.display(&interpreter.context.display)),
+        }
+    }
+}
+
+/// How many lines of REPL history are kept unless `ALEPH_HISTORY_SIZE` says otherwise.
+const DEFAULT_HISTORY_SIZE: usize = 1000;
+
+/// The line editor's settings, keeping as many lines of history as `ALEPH_HISTORY_SIZE` says.
+fn editor_config() -> Config {
+    let size = std::env::var("ALEPH_HISTORY_SIZE")
+        .ok()
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(DEFAULT_HISTORY_SIZE);
+    Config::builder()
+        .max_history_size(size)
+        .and_then(|builder| builder.history_ignore_dups(false))
+        .expect("history settings are always valid")
+        .build()
+}
+
+/// Where interactive sessions keep their history between runs.
+fn history_file() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".aleph_history"))
+}
+
+/// Records a line of input in the history. An input continued over several lines is
+/// recorded as one, joined by spaces.
+fn record(history: &mut DefaultHistory, input: &str) {
+    let line = input.lines().map(str::trim).collect::<Vec<_>>().join(" ");
+    if let Err(e) = history.add(&line) {
+        eprintln!("warning: could not record history: {}", e);
+    }
+}
+
+fn run_command(interpreter: &mut Interpreter, history: &DefaultHistory, command: &str) {
+    let (name, argument) = command.split_once(' ').unwrap_or((command, ""));
+    match name {
+        "explain" => {
+            explain(interpreter, argument);
+        }
+        "load" => load(interpreter, argument.trim()),
+        "profile" => profile(interpreter, argument),
+        "full" => match argument.trim() {
+            "" | "on" => {
+                interpreter.context.display.max_real_length = None;
+                interpreter.context.display.max_list_length = None;
+            }
+            "off" => {
+                let defaults = DisplaySettings::default();
+                interpreter.context.display.max_real_length = defaults.max_real_length;
+                interpreter.context.display.max_list_length = defaults.max_list_length;
+            }
+            other => eprintln!("error: expected on or off, found {}", other),
+        },
+        "format" => match argument.trim() {
+            "" => interpreter.context.display.significant_digits = None,
+            digits => match digits.parse() {
+                Ok(digits) if digits > 0 => {
+                    interpreter.context.display.significant_digits = Some(digits)
+                }
+                _ => eprintln!(
+                    "error: expected a positive number of digits, found {}",
+                    digits
+                ),
+            },
+        },
+        "frac" => match argument.trim() {
+            "" | "on" => interpreter.context.display.fractions = true,
+            "off" => interpreter.context.display.fractions = false,
+            other => eprintln!("error: expected on or off, found {}", other),
+        },
+        "sci" => match argument.split_whitespace().collect::<Vec<_>>()[..] {
+            [] | ["on"] => {
+                interpreter.context.display.scientific = Some(ScientificRange::default())
+            }
+            ["off"] => interpreter.context.display.scientific = None,
+            [min, max] => match (min.parse(), max.parse()) {
+                (Ok(min_exponent), Ok(max_exponent)) => {
+                    interpreter.context.display.scientific = Some(ScientificRange {
+                        min_exponent,
+                        max_exponent,
+                    })
+                }
+                _ => eprintln!("error: expected two exponents, found {} {}", min, max),
+            },
+            _ => eprintln!("error: expected on, off or two exponents"),
+        },
+        "rounding" => match argument.trim() {
+            "" | "default" => interpreter.context.rounding = None,
+            "zero" => interpreter.context.rounding = Some(Rounding::Zero),
+            "nearest" => interpreter.context.rounding = Some(Rounding::Nearest),
+            "up" => interpreter.context.rounding = Some(Rounding::Up),
+            "down" => interpreter.context.rounding = Some(Rounding::Down),
+            other => eprintln!(
+                "error: expected zero, nearest, up, down or default, found {}",
+                other
+            ),
+        },
+        "overflow" => match argument.trim() {
+            "" | "promote" => interpreter.context.overflow = OverflowMode::Promote,
+            "wrap" => interpreter.context.overflow = OverflowMode::Wrap,
+            "error" => interpreter.context.overflow = OverflowMode::Error,
+            other => eprintln!("error: expected promote, wrap or error, found {}", other),
+        },
+        "trace" => match argument.trim() {
+            "" | "on" => interpreter.context.trace_depth = Some(0),
+            "off" => interpreter.context.trace_depth = None,
+            other => eprintln!("error: expected on or off, found {}", other),
+        },
+        "limit" => match argument.trim() {
+            "off" => interpreter.context.recursion_limit = None,
+            "" => interpreter.context.recursion_limit = Some(DEFAULT_RECURSION_LIMIT),
+            limit => match limit.parse() {
+                Ok(limit) => interpreter.context.recursion_limit = Some(limit),
+                Err(_) => eprintln!("error: expected a number of calls or off, found {}", limit),
+            },
+        },
+        "rangelimit" => match argument.trim() {
+            "off" => interpreter.context.max_range_length = None,
+            "" => interpreter.context.max_range_length = Some(DEFAULT_MAX_RANGE_LENGTH),
+            limit => match limit.parse() {
+                Ok(limit) => interpreter.context.max_range_length = Some(limit),
+                Err(_) => eprintln!(
+                    "error: expected a number of elements or off, found {}",
+                    limit
+                ),
+            },
+        },
+        "help" => help(argument.trim()),
+        "vars" => list_variables(interpreter),
+        "history" => {
+            for (number, line) in history.iter().enumerate() {
+                println!("{:5}  {}", number + 1, line);
+            }
+        }
+        _ => eprintln!("error: unknown command :{}", name),
+    }
+}
+
+/// Splits `input` at every `;` that isn't inside brackets, braces, a string or a comment, or
+/// after a `where`, whose bindings are themselves separated by `;`. Also returns whether a
+/// bracket, brace, string or block comment is still open at the end, so that more input is
+/// needed to finish it.
+fn split_statements(input: &str) -> (Vec<&str>, bool) {
+    let mut segments = Vec::new();
+    let mut depth = 0usize;
+    let mut in_string = false;
+    // The `#`s closing the raw string being read, if it is one.
+    let mut raw_terminator = None;
+    let mut in_line_comment = false;
+    // Block comments nest, as they do in the parser.
+    let mut comment_depth = 0usize;
+    let mut in_where = false;
+    let mut escaped = false;
+    let mut start = 0;
+    let mut chars = input.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        if in_line_comment {
+            in_line_comment = c != '\n';
+            continue;
+        }
+        if comment_depth > 0 {
+            match (c, chars.peek()) {
+                ('*', Some((_, '/'))) => {
+                    chars.next();
+                    comment_depth -= 1;
+                }
+                ('/', Some((_, '*'))) => {
+                    chars.next();
+                    comment_depth += 1;
+                }
+                _ => {}
+            }
+            continue;
+        }
+        if in_string {
+            match (c, raw_terminator) {
+                ('"', Some(hashes)) if input[index + 1..].starts_with(hashes) => in_string = false,
+                (_, Some(_)) => {}
+                _ if escaped => escaped = false,
+                ('\\', _) => escaped = true,
+                ('"', _) => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => {
+                in_string = true;
+                raw_terminator = raw_string_hashes(&input[..index]);
+            }
+            '#' if starts_line_comment(input, index) => in_line_comment = true,
+            '/' if matches!(chars.peek(), Some((_, '*'))) => {
+                chars.next();
+                comment_depth = 1;
+            }
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            'w' if depth == 0 && starts_word(input, index, "where") => in_where = true,
+            // Within a `where` clause, `;` separates its bindings, so the clause only ends at
+            // one that isn't followed by another binding.
+            ';' if depth == 0 && !(in_where && starts_binding(&input[index + 1..])) => {
+                segments.push(&input[start..index]);
+                start = index + 1;
+                in_where = false;
+            }
+            _ => {}
+        }
+    }
+    segments.push(&input[start..]);
+    (segments, depth > 0 || in_string || comment_depth > 0)
+}
+
+/// Whether `input` starts with a binding such as `y = x` or `(a, b) = p`, rather than `y == x`
+/// or a function definition.
+fn starts_binding(input: &str) -> bool {
+    let input = input.trim_start();
+    let target_length = if input.starts_with('(') {
+        let mut depth = 0usize;
+        input
+            .find(|c| {
+                match c {
+                    '(' => depth += 1,
+                    ')' => depth -= 1,
+                    _ => {}
+                }
+                depth == 0
+            })
+            .map(|close| close + 1)
+    } else {
+        input.find(|c| !is_name_char(c))
+    };
+    let rest = input[target_length.unwrap_or(input.len())..].trim_start();
+    target_length != Some(0) && rest.starts_with('=') && !rest.starts_with("==")
+}
+
+/// Whether the `#` at `index` starts a comment, rather than being part of `$#` or of the `r#"`
+/// that opens a raw string.
+fn starts_line_comment(input: &str, index: usize) -> bool {
+    let before = &input[..index];
+    let opens_raw_string = raw_string_hashes(before).is_some()
+        && input[index..].trim_start_matches('#').starts_with('"');
+    !before.ends_with('$') && !opens_raw_string
+}
+
+/// Gathers REPL lines into complete inputs: while a line leaves a bracket, string or block
+/// comment open, the lines after it continue it, until everything is closed or a blank line
+/// abandons it.
+#[derive(Default)]
+struct Continuation {
+    pending: String,
+}
+
+impl Continuation {
+    /// Adds a line, returning the input it completes, if any.
+    fn push(&mut self, line: &str) -> Option<String> {
+        if !self.pending.is_empty() && line.trim().is_empty() {
+            eprintln!(
+                "warning: discarded unfinished input: {}",
+                self.pending.trim_end()
+            );
+            self.pending.clear();
+            return None;
+        }
+        self.pending.push_str(line.trim_end_matches('\n'));
+        self.pending.push('\n');
+        let (_, unfinished) = split_statements(&self.pending);
+        if unfinished {
+            None
+        } else {
+            Some(std::mem::take(&mut self.pending))
+        }
+    }
+
+    /// Whatever is left unfinished at the end of the input, so that its error is reported.
+    fn finish(self) -> Option<String> {
+        Some(self.pending).filter(|pending| !pending.is_empty())
+    }
+}
+
+/// If `before` ends in the `r#` that opens a raw string, the `#`s that must close it.
+fn raw_string_hashes(before: &str) -> Option<&str> {
+    let prefix = before.trim_end_matches('#');
+    let hashes = &before[prefix.len()..];
+    let prefix = prefix.strip_suffix('r')?;
+    (!prefix.ends_with(is_name_char)).then_some(hashes)
+}
+
+/// Whether `word` appears in `input` at `index` as a whole word rather than part of a name.
+fn starts_word(input: &str, index: usize, word: &str) -> bool {
+    let before = input[..index].chars().next_back();
+    let after = input[index..]
+        .strip_prefix(word)
+        .map(|rest| rest.chars().next());
+    !before.is_some_and(is_name_char) && after.is_some_and(|after| !after.is_some_and(is_name_char))
+}
+
+/// Handles one line of REPL or batch input: a command, or `;`-separated definitions and
+/// expressions, each of which prints its own result or error. Ending the line with `;`
+/// keeps the last result from being printed.
+fn run_line(interpreter: &mut Interpreter, history: &mut DefaultHistory, input: &str) {
+    record(history, input);
+    if let Some(command) = input.trim().strip_prefix(':') {
+        run_command(interpreter, history, command);
+        return;
+    }
+    let (mut statements, _) = split_statements(input);
+    let quiet = statements.len() > 1 && statements.last().unwrap().trim().is_empty();
+    if quiet {
+        statements.pop();
+    }
+    let last = statements.len() - 1;
+    for (index, statement) in statements.into_iter().enumerate() {
+        run_statement(interpreter, statement, !(quiet && index == last));
+    }
+}
+
+/// The REPL binds each expression's value to this name, so the next line can use it.
+const LAST_RESULT: &str = "_";
+
+/// Defines or evaluates one statement, printing an expression's value only if `echo` is set.
+/// Returns whether it succeeded.
+fn run_statement(interpreter: &mut Interpreter, input: &str, echo: bool) -> bool {
+    if input.trim().is_empty() {
+        return true;
+    }
+    match parse_complete_definition(input) {
+        Ok(definition) => define(interpreter, definition),
+        _ => match parse_complete_expression(input) {
+            Ok(expression) => match interpreter.evaluate(&expression) {
+                Ok(value) => {
+                    if echo {
+                        println!("{}", value.display(&interpreter.context.display));
+                    }
+                    interpreter.variables.insert(LAST_RESULT.to_string(), value);
+                    true
+                }
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    false
+                }
+            },
+            Err(e) => {
+                eprintln!("error: {}", e);
+                false
+            }
+        },
+    }
+}
+
+fn main() {
+    let mut interpreter = Interpreter::new();
+    let mut ast_json = false;
+    let mut batch = false;
+    let mut check = false;
+    let mut expression = None;
+    let mut explaining = false;
+    let mut file_names = Vec::new();
+    let mut positional = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ast-json" => ast_json = true,
+            "--batch" => batch = true,
+            "--check" => check = true,
+            "-e" => match args.next() {
+                Some(source) => expression = Some(source),
+                None => {
+                    eprintln!("error: -e requires an expression");
+                    std::process::exit(2);
+                }
+            },
+            // Like `-e`, but shows where the expression fails, as `:explain` does.
+            "--explain" => match args.next() {
+                Some(source) => {
+                    expression = Some(source);
+                    explaining = true;
+                }
+                None => {
+                    eprintln!("error: --explain requires an expression");
+                    std::process::exit(2);
+                }
+            },
+            // Files come before `-e`; whatever follows its expression is bound to `$1`, `$2`...
+            _ if expression.is_some() => positional.push(arg),
+            _ => file_names.push(arg),
+        }
+    }
+    if check {
+        let mut clean = true;
+        for file_name in &file_names {
+            let diagnostics = match std::fs::read_to_string(file_name) {
+                Ok(source) => interpreter.check(&source),
+                Err(e) => {
+                    eprintln!("error: {}: {}", file_name, e);
+                    std::process::exit(1);
+                }
+            };
+            for diagnostic in &diagnostics {
+                eprintln!("error: {}: {}", file_name, diagnostic);
+            }
+            clean &= diagnostics.is_empty();
+        }
+        std::process::exit(if clean { 0 } else { 1 });
+    }
+    let mut statements = Vec::new();
+    for file_name in &file_names {
+        match parse_file(file_name) {
+            Ok(top_level) => statements.extend(top_level),
+            Err(e) => {
+                eprintln!("error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    if ast_json {
+        println!(
+            "{}",
+            Json::Array(statements.iter().map(Json::from).collect())
+        );
+        return;
+    }
+    reject_cycles(&interpreter, &mut statements);
+    warn_unbound_variables(&interpreter, &statements);
+    for item in statements {
+        define(&mut interpreter, item);
+    }
+    if let Some(source) = expression {
+        bind_positional(&mut interpreter, &positional);
+        if explaining {
+            std::process::exit(if explain(&mut interpreter, &source) {
+                0
+            } else {
+                1
+            });
+        }
+        // Like a REPL line, the expression can start with `;`-separated definitions.
+        let (statements, _) = split_statements(&source);
+        for statement in statements {
+            if !run_statement(&mut interpreter, statement, true) {
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    if batch {
+        let mut history = DefaultHistory::with_config(&editor_config());
+        let mut continuation = Continuation::default();
+        for line in std::io::stdin().lines() {
+            if let Some(input) = continuation.push(&line.unwrap()) {
+                run_line(&mut interpreter, &mut history, &input);
+            }
+        }
+        if let Some(input) = continuation.finish() {
+            run_line(&mut interpreter, &mut history, &input);
+        }
+        return;
+    }
+    let mut editor = match DefaultEditor::with_config(editor_config()) {
+        Ok(editor) => editor,
+        Err(e) => {
+            eprintln!("error: could not start the line editor: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut history_file = history_file();
+    if let Some(file) = &history_file {
+        // There is no history to load before the first session.
+        let _ = editor.load_history(file);
+    }
+    let mut continuation = Continuation::default();
     loop {
-        let mut input = String::new();
-        std::io::stdin().read_line(&mut input).unwrap();
-        let (_, expression) = parse_expression(&input).unwrap();
-        let result = Value::evaluate(&variables, &expression);
-        match result {
-            Ok(value) => println!("{}", value),
-            Err(e) => eprintln!("error: {}", e),
+        let (input, finished) = match editor.readline("") {
+            Ok(line) => (continuation.push(&line), false),
+            // Ctrl-C abandons whatever has been typed so far.
+            Err(ReadlineError::Interrupted) => {
+                continuation = Continuation::default();
+                continue;
+            }
+            Err(ReadlineError::Eof) => (std::mem::take(&mut continuation).finish(), true),
+            Err(e) => {
+                eprintln!("error: {}", e);
+                break;
+            }
+        };
+        if let Some(input) = input {
+            run_line(&mut interpreter, editor.history_mut(), &input);
+        }
+        // Only the new lines are written. A file that can't be written only costs the
+        // history, so the error is reported once and otherwise ignored.
+        if let Some(file) = &history_file {
+            if let Err(e) = editor.append_history(file) {
+                eprintln!(
+                    "warning: could not save history to {}: {}",
+                    file.display(),
+                    e
+                );
+                history_file = None;
+            }
+        }
+        if finished {
+            println!();
+            break;
         }
     }
 }
@@ -1,35 +1,186 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+
+use dashu_float::DBig;
 use nom::{
     branch::alt,
-    bytes::complete::tag,
-    character::complete::{alpha1, char, digit1, multispace0},
-    combinator::map_res,
-    error::ParseError,
-    multi::{fold_many1, many0, separated_list0},
-    sequence::delimited,
+    bytes::complete::{escaped_transform, tag, take_until},
+    character::complete::{char, digit1, multispace1, none_of, not_line_ending, one_of, satisfy},
+    combinator::{map_res, not, opt, peek, recognize, value},
+    error::{ErrorKind, ParseError},
+    multi::{fold_many0, many0, many0_count, separated_list0, separated_list1},
+    sequence::{delimited, pair, preceded, terminated, tuple},
     IResult, Parser,
 };
 
 #[derive(Debug, Clone)]
 pub enum Type {
     Named(String),
+    /// The domain of a function of several parameters, such as `(Int, Int)`.
+    Product(Vec<Type>),
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Type::Named(name) => write!(f, "{}", name),
+            Type::Product(types) => {
+                write!(f, "(")?;
+                for (index, typ) in types.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", typ)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+/// Where an expression appears in the source it was parsed from: a byte range, along with the
+/// line and column (both starting at 1) of its start.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// The line and column, both starting at 1, of the byte `offset` in `source`.
+fn line_and_column(source: &str, offset: usize) -> (usize, usize) {
+    let consumed = &source[..offset];
+    let line = consumed.matches('\n').count() + 1;
+    let line_start = consumed.rfind('\n').map_or(0, |index| index + 1);
+    (line, consumed[line_start..].chars().count() + 1)
 }
 
 #[derive(Debug, Clone)]
 pub enum Expression {
     Integer(i64),
-    Variable(String),
+    /// A decimal literal, kept exactly as written.
+    Real(DBig),
+    Bool(bool),
+    Str(String),
+    /// A name, with where it was written unless the parser made it up.
+    Variable {
+        name: String,
+        span: Option<Span>,
+    },
 
     Negate(Box<Expression>),
+    /// `|x|`, the same as calling the `abs` builtin.
+    Abs(Box<Expression>),
     Add(Box<Expression>, Box<Expression>),
     Subtract(Box<Expression>, Box<Expression>),
     Multiply(Box<Expression>, Box<Expression>),
     Divide(Box<Expression>, Box<Expression>),
+    IntegerDivide(Box<Expression>, Box<Expression>),
     Power(Box<Expression>, Box<Expression>),
+    Factorial(Box<Expression>),
+
+    Equal(Box<Expression>, Box<Expression>),
+    NotEqual(Box<Expression>, Box<Expression>),
+    LessThan(Box<Expression>, Box<Expression>),
+    LessThanOrEqual(Box<Expression>, Box<Expression>),
+    GreaterThan(Box<Expression>, Box<Expression>),
+    GreaterThanOrEqual(Box<Expression>, Box<Expression>),
+    BitAnd(Box<Expression>, Box<Expression>),
+    BitOr(Box<Expression>, Box<Expression>),
+    BitXor(Box<Expression>, Box<Expression>),
+    ShiftLeft(Box<Expression>, Box<Expression>),
+    ShiftRight(Box<Expression>, Box<Expression>),
+    /// Evaluates its second operand only if the first is true.
+    And(Box<Expression>, Box<Expression>),
+
+    /// Evaluates only the branch selected by `condition`.
+    If {
+        condition: Box<Expression>,
+        then: Box<Expression>,
+        otherwise: Box<Expression>,
+    },
+
+    /// `x if x >= 0 | -x otherwise`, the body of a function defined piecewise: the result of
+    /// the first branch whose condition holds, or else of `otherwise`.
+    Guarded {
+        branches: Vec<(Expression, Expression)>,
+        otherwise: Option<Box<Expression>>,
+    },
+    /// `match n { 0 => a, _ => b }`: the first arm whose pattern matches the scrutinee.
+    Match {
+        scrutinee: Box<Expression>,
+        arms: Vec<(Pattern, Expression)>,
+    },
+
+    List(Vec<Expression>),
+    /// `(a, b)`; a tuple of one element is written `(a,)`.
+    Tuple(Vec<Expression>),
+    Index {
+        list: Box<Expression>,
+        index: Box<Expression>,
+    },
 
     ApplyFunction {
         function: Box<Expression>,
         arguments: Vec<Expression>,
     },
+    /// `{ a = 1; b = a + 1; b }`: bindings visible to later bindings and the result only.
+    Block {
+        bindings: Vec<(String, Expression)>,
+        result: Box<Expression>,
+    },
+    Lambda {
+        parameters: Vec<String>,
+        /// Conditions checked once the arguments are bound, paired with the parameter they guard.
+        guards: Vec<(String, Expression)>,
+        body: Box<Expression>,
+    },
+}
+
+/// What a `match` arm accepts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pattern {
+    /// A number equal to this one.
+    Integer(i64),
+    /// `_`, which accepts anything.
+    Wildcard,
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Pattern::Integer(value) => write!(f, "{}", value),
+            Pattern::Wildcard => write!(f, "{}", IGNORED_PARAMETER),
+        }
+    }
+}
+
+/// Why a `match` arm can never be chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnreachableArm {
+    /// An earlier arm is `_`, which accepts anything.
+    AfterWildcard,
+    /// An earlier arm has the same pattern.
+    Duplicate,
+}
+
+impl Display for UnreachableArm {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            UnreachableArm::AfterWildcard => write!(f, "it follows {}", IGNORED_PARAMETER),
+            UnreachableArm::Duplicate => write!(f, "an earlier arm has the same pattern"),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -43,21 +194,612 @@ pub enum TopLevelStatement {
         name: String,
         parameters: Vec<String>,
         body: Expression,
+        /// `where a = x * 2; b = x + 1`: local definitions, in order, that can use the
+        /// parameters and earlier bindings.
+        bindings: Vec<(String, Expression)>,
+    },
+    VariableDefinition {
+        name: String,
+        value: Expression,
     },
+    /// A bare expression, evaluated for its value. Only [`Statements::with_expressions`]
+    /// parses these; programs are made of definitions.
+    Expression(Expression),
+}
+
+impl Expression {
+    fn binary_parts(&self) -> Option<(&'static str, &Expression, &Expression)> {
+        Some(match self {
+            Expression::Add(a, b) => ("+", a, b),
+            Expression::Subtract(a, b) => ("-", a, b),
+            Expression::Multiply(a, b) => ("*", a, b),
+            Expression::Divide(a, b) => ("/", a, b),
+            Expression::IntegerDivide(a, b) => ("//", a, b),
+            Expression::Power(a, b) => ("^", a, b),
+            Expression::Equal(a, b) => ("==", a, b),
+            Expression::NotEqual(a, b) => ("!=", a, b),
+            Expression::LessThan(a, b) => ("<", a, b),
+            Expression::LessThanOrEqual(a, b) => ("<=", a, b),
+            Expression::GreaterThan(a, b) => (">", a, b),
+            Expression::GreaterThanOrEqual(a, b) => (">=", a, b),
+            Expression::BitAnd(a, b) => ("&", a, b),
+            Expression::BitOr(a, b) => ("|", a, b),
+            Expression::BitXor(a, b) => ("xor", a, b),
+            Expression::ShiftLeft(a, b) => ("<<", a, b),
+            Expression::ShiftRight(a, b) => (">>", a, b),
+            Expression::And(a, b) => ("and", a, b),
+            _ => return None,
+        })
+    }
+
+    /// The subexpressions evaluated to compute this one, in evaluation order.
+    pub fn operands(&self) -> Vec<&Expression> {
+        if let Some((_, a, b)) = self.binary_parts() {
+            return vec![a, b];
+        }
+        match self {
+            Expression::Negate(a) | Expression::Abs(a) | Expression::Factorial(a) => vec![a],
+            Expression::If { condition, .. } => vec![condition],
+            Expression::Guarded { branches, .. } => vec![&branches[0].0],
+            Expression::Match { scrutinee, .. } => vec![scrutinee],
+            Expression::List(elements) | Expression::Tuple(elements) => elements.iter().collect(),
+            Expression::Index { list, index } => vec![list, index],
+            Expression::ApplyFunction {
+                function,
+                arguments,
+            } => std::iter::once(&**function).chain(arguments).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// The names this expression refers to without binding them itself.
+    pub fn free_variables(&self) -> BTreeSet<String> {
+        self.free_variable_spans().into_keys().collect()
+    }
+
+    /// The patterns of the `match` arms anywhere in this expression that can never be chosen,
+    /// with the reason for each.
+    pub fn unreachable_arms(&self) -> Vec<(&Pattern, UnreachableArm)> {
+        let mut unreachable = Vec::new();
+        if let Expression::Match { arms, .. } = self {
+            let mut seen = Vec::new();
+            for (pattern, _) in arms {
+                if seen.contains(&&Pattern::Wildcard) {
+                    unreachable.push((pattern, UnreachableArm::AfterWildcard));
+                } else if seen.contains(&pattern) {
+                    unreachable.push((pattern, UnreachableArm::Duplicate));
+                }
+                seen.push(pattern);
+            }
+        }
+        for subexpression in self.subexpressions() {
+            unreachable.extend(subexpression.unreachable_arms());
+        }
+        unreachable
+    }
+
+    /// Like [`Expression::free_variables`], along with where each name is first used freely.
+    pub fn free_variable_spans(&self) -> BTreeMap<String, Option<Span>> {
+        let mut free = BTreeMap::new();
+        match self {
+            Expression::Variable { name, span } => {
+                free.insert(name.clone(), *span);
+            }
+            Expression::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                for operand in [condition, then, otherwise] {
+                    add_free_variables(&mut free, operand, &[]);
+                }
+            }
+            Expression::Guarded { .. } => {
+                for subexpression in self.subexpressions() {
+                    add_free_variables(&mut free, subexpression, &[]);
+                }
+            }
+            Expression::Match { scrutinee, arms } => {
+                add_free_variables(&mut free, scrutinee, &[]);
+                for (_, result) in arms {
+                    add_free_variables(&mut free, result, &[]);
+                }
+            }
+            Expression::Block { bindings, result } => {
+                let mut bound = Vec::new();
+                for (name, value) in bindings {
+                    add_free_variables(&mut free, value, &bound);
+                    bound.push(name);
+                }
+                add_free_variables(&mut free, result, &bound);
+            }
+            Expression::Lambda {
+                parameters,
+                guards,
+                body,
+            } => {
+                let parameters: Vec<_> = parameters.iter().collect();
+                for (_, guard) in guards {
+                    add_free_variables(&mut free, guard, &parameters);
+                }
+                add_free_variables(&mut free, body, &parameters);
+            }
+            expression => {
+                for operand in expression.operands() {
+                    add_free_variables(&mut free, operand, &[]);
+                }
+            }
+        }
+        free
+    }
+
+    /// Every direct subexpression, including branches and bodies that `operands` leaves out.
+    pub fn subexpressions(&self) -> Vec<&Expression> {
+        match self {
+            Expression::Integer(_)
+            | Expression::Real(_)
+            | Expression::Bool(_)
+            | Expression::Str(_)
+            | Expression::Variable { .. } => Vec::new(),
+            Expression::Negate(a) | Expression::Abs(a) | Expression::Factorial(a) => vec![a],
+            Expression::Add(a, b)
+            | Expression::Subtract(a, b)
+            | Expression::Multiply(a, b)
+            | Expression::Divide(a, b)
+            | Expression::IntegerDivide(a, b)
+            | Expression::Power(a, b)
+            | Expression::Equal(a, b)
+            | Expression::NotEqual(a, b)
+            | Expression::LessThan(a, b)
+            | Expression::LessThanOrEqual(a, b)
+            | Expression::GreaterThan(a, b)
+            | Expression::GreaterThanOrEqual(a, b)
+            | Expression::BitAnd(a, b)
+            | Expression::BitOr(a, b)
+            | Expression::BitXor(a, b)
+            | Expression::ShiftLeft(a, b)
+            | Expression::ShiftRight(a, b)
+            | Expression::And(a, b) => vec![a, b],
+            Expression::If {
+                condition,
+                then,
+                otherwise,
+            } => vec![condition, then, otherwise],
+            Expression::Guarded {
+                branches,
+                otherwise,
+            } => branches
+                .iter()
+                .flat_map(|(condition, result)| [condition, result])
+                .chain(otherwise.as_deref())
+                .collect(),
+            Expression::Match { scrutinee, arms } => std::iter::once(&**scrutinee)
+                .chain(arms.iter().map(|(_, result)| result))
+                .collect(),
+            Expression::List(elements) | Expression::Tuple(elements) => elements.iter().collect(),
+            Expression::Index { list, index } => vec![list, index],
+            Expression::ApplyFunction {
+                function,
+                arguments,
+            } => std::iter::once(&**function)
+                .chain(arguments.iter())
+                .collect(),
+            Expression::Block { bindings, result } => bindings
+                .iter()
+                .map(|(_, value)| value)
+                .chain(std::iter::once(&**result))
+                .collect(),
+            Expression::Lambda { guards, body, .. } => guards
+                .iter()
+                .map(|(_, guard)| guard)
+                .chain(std::iter::once(&**body))
+                .collect(),
+        }
+    }
+
+    /// The same as [`Expression::subexpressions`], but mutable.
+    fn subexpressions_mut(&mut self) -> Vec<&mut Expression> {
+        match self {
+            Expression::Integer(_)
+            | Expression::Real(_)
+            | Expression::Bool(_)
+            | Expression::Str(_)
+            | Expression::Variable { .. } => Vec::new(),
+            Expression::Negate(a) | Expression::Abs(a) | Expression::Factorial(a) => vec![a],
+            Expression::Add(a, b)
+            | Expression::Subtract(a, b)
+            | Expression::Multiply(a, b)
+            | Expression::Divide(a, b)
+            | Expression::IntegerDivide(a, b)
+            | Expression::Power(a, b)
+            | Expression::Equal(a, b)
+            | Expression::NotEqual(a, b)
+            | Expression::LessThan(a, b)
+            | Expression::LessThanOrEqual(a, b)
+            | Expression::GreaterThan(a, b)
+            | Expression::GreaterThanOrEqual(a, b)
+            | Expression::BitAnd(a, b)
+            | Expression::BitOr(a, b)
+            | Expression::BitXor(a, b)
+            | Expression::ShiftLeft(a, b)
+            | Expression::ShiftRight(a, b)
+            | Expression::And(a, b) => vec![a, b],
+            Expression::If {
+                condition,
+                then,
+                otherwise,
+            } => vec![condition, then, otherwise],
+            Expression::Guarded {
+                branches,
+                otherwise,
+            } => branches
+                .iter_mut()
+                .flat_map(|(condition, result)| [condition, result])
+                .chain(otherwise.as_deref_mut())
+                .collect(),
+            Expression::Match { scrutinee, arms } => std::iter::once(&mut **scrutinee)
+                .chain(arms.iter_mut().map(|(_, result)| result))
+                .collect(),
+            Expression::List(elements) | Expression::Tuple(elements) => {
+                elements.iter_mut().collect()
+            }
+            Expression::Index { list, index } => vec![list, index],
+            Expression::ApplyFunction {
+                function,
+                arguments,
+            } => std::iter::once(&mut **function)
+                .chain(arguments.iter_mut())
+                .collect(),
+            Expression::Block { bindings, result } => bindings
+                .iter_mut()
+                .map(|(_, value)| value)
+                .chain(std::iter::once(&mut **result))
+                .collect(),
+            Expression::Lambda { guards, body, .. } => guards
+                .iter_mut()
+                .map(|(_, guard)| guard)
+                .chain(std::iter::once(&mut **body))
+                .collect(),
+        }
+    }
+
+    /// Turns the spans recorded while parsing, which count back from the end of the input,
+    /// into positions in `source`.
+    fn resolve_spans(&mut self, source: &str) {
+        if let Expression::Variable {
+            span: Some(span), ..
+        } = self
+        {
+            let (start, end) = (source.len() - span.start, source.len() - span.end);
+            let (line, column) = line_and_column(source, start);
+            *span = Span {
+                start,
+                end,
+                line,
+                column,
+            };
+        }
+        for subexpression in self.subexpressions_mut() {
+            subexpression.resolve_spans(source);
+        }
+    }
+
+    fn is_atomic(&self) -> bool {
+        matches!(
+            self,
+            Expression::Integer(_)
+                | Expression::Real(_)
+                | Expression::Bool(_)
+                | Expression::Str(_)
+                | Expression::Variable { .. }
+                | Expression::Factorial(_)
+                | Expression::Abs(_)
+                | Expression::List(_)
+                | Expression::Tuple(_)
+                | Expression::Index { .. }
+                | Expression::Block { .. }
+                | Expression::Match { .. }
+                | Expression::ApplyFunction { .. }
+        )
+    }
+}
+
+/// Adds the free variables of `expression` that aren't `bound` to `free`, keeping any earlier
+/// span for names already there.
+fn add_free_variables(
+    free: &mut BTreeMap<String, Option<Span>>,
+    expression: &Expression,
+    bound: &[&String],
+) {
+    for (name, span) in expression.free_variable_spans() {
+        if !bound.contains(&&name) {
+            free.entry(name).or_insert(span);
+        }
+    }
+}
+
+fn write_operand(f: &mut Formatter, operand: &Expression) -> fmt::Result {
+    if operand.is_atomic() {
+        write!(f, "{}", operand)
+    } else {
+        write!(f, "({})", operand)
+    }
+}
+
+/// Writes part of a guarded body, bracketing a `|` that would otherwise separate branches.
+fn write_branch_part(f: &mut Formatter, part: &Expression) -> fmt::Result {
+    if matches!(part, Expression::BitOr(..)) {
+        write!(f, "({})", part)
+    } else {
+        write!(f, "{}", part)
+    }
+}
+
+fn write_separated(f: &mut Formatter, items: &[Expression]) -> fmt::Result {
+    for (index, item) in items.iter().enumerate() {
+        if index > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if let Some((operator, a, b)) = self.binary_parts() {
+            write_operand(f, a)?;
+            write!(f, " {} ", operator)?;
+            return write_operand(f, b);
+        }
+        match self {
+            Expression::Integer(value) => write!(f, "{}", value),
+            Expression::Real(value) => write!(f, "{}", value),
+            Expression::Bool(value) => write!(f, "{}", value),
+            Expression::Str(value) => {
+                write!(f, "\"")?;
+                for c in value.chars() {
+                    match c {
+                        '"' => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        '\t' => write!(f, "\\t")?,
+                        c => write!(f, "{}", c)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Expression::Variable { name, .. } => write!(f, "{}", name),
+            Expression::Negate(a) => {
+                write!(f, "-")?;
+                write_operand(f, a)
+            }
+            Expression::Abs(a) => write!(f, "|{}|", a),
+            Expression::Factorial(a) => {
+                write_operand(f, a)?;
+                write!(f, "!")
+            }
+            Expression::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                write_operand(f, condition)?;
+                write!(f, " ? ")?;
+                write_operand(f, then)?;
+                write!(f, " : ")?;
+                write_operand(f, otherwise)
+            }
+            Expression::Guarded {
+                branches,
+                otherwise,
+            } => {
+                for (index, (condition, result)) in branches.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, " | ")?;
+                    }
+                    write_branch_part(f, result)?;
+                    write!(f, " if ")?;
+                    write_branch_part(f, condition)?;
+                }
+                if let Some(otherwise) = otherwise {
+                    write!(f, " | ")?;
+                    write_branch_part(f, otherwise)?;
+                    write!(f, " otherwise")?;
+                }
+                Ok(())
+            }
+            Expression::Match { scrutinee, arms } => {
+                write!(f, "match {} {{ ", scrutinee)?;
+                for (index, (pattern, result)) in arms.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{} => {}", pattern, result)?;
+                }
+                write!(f, " }}")
+            }
+            Expression::List(elements) => {
+                write!(f, "[")?;
+                write_separated(f, elements)?;
+                write!(f, "]")
+            }
+            Expression::Tuple(elements) => {
+                write!(f, "(")?;
+                write_separated(f, elements)?;
+                if elements.len() == 1 {
+                    write!(f, ",")?;
+                }
+                write!(f, ")")
+            }
+            Expression::Index { list, index } => {
+                write_operand(f, list)?;
+                write!(f, "[{}]", index)
+            }
+            Expression::ApplyFunction {
+                function,
+                arguments,
+            } => {
+                write_operand(f, function)?;
+                write!(f, "(")?;
+                write_separated(f, arguments)?;
+                write!(f, ")")
+            }
+            Expression::Block { bindings, result } => {
+                write!(f, "{{ ")?;
+                for (name, value) in bindings {
+                    write!(f, "{} = {}; ", name, value)?;
+                }
+                write!(f, "{} }}", result)
+            }
+            Expression::Lambda {
+                parameters,
+                guards,
+                body,
+            } => {
+                write!(f, "(")?;
+                for (index, parameter) in parameters.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", parameter)?;
+                    for (_, guard) in guards.iter().filter(|(name, _)| name == parameter) {
+                        write!(f, " where {}", guard)?;
+                    }
+                }
+                write!(f, ") => {}", body)
+            }
+            _ => unreachable!("binary operators are handled above"),
+        }
+    }
+}
+
+/// A parse failure, located by line and column (both starting at 1) in the original source.
+#[derive(Debug, Clone)]
+pub struct SyntaxError {
+    pub line: usize,
+    pub column: usize,
+    /// What was found where parsing stopped.
+    pub message: String,
+}
+
+impl SyntaxError {
+    /// Locates the error at the start of `remaining`, which must be a suffix of `source`.
+    pub fn at(source: &str, remaining: &str) -> SyntaxError {
+        let remaining = remaining.trim_start();
+        let (line, column) = line_and_column(source, source.len() - remaining.len());
+        let message = match remaining.chars().next() {
+            Some(c) => format!("unexpected '{}'", c),
+            None => "unexpected end of input".to_string(),
+        };
+        SyntaxError {
+            line,
+            column,
+            message,
+        }
+    }
+
+    fn from_nom(source: &str, error: nom::Err<nom::error::Error<&str>>) -> SyntaxError {
+        match error {
+            nom::Err::Error(e) | nom::Err::Failure(e) => SyntaxError::at(source, e.input),
+            nom::Err::Incomplete(_) => SyntaxError::at(source, ""),
+        }
+    }
+}
+
+impl Display for SyntaxError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "parse error at line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl std::error::Error for SyntaxError {}
+
+/// Parses a `/* ... */` comment, which may contain nested block comments.
+/// An unterminated comment is a failure reported at its opening `/*`.
+fn block_comment<'a, E: ParseError<&'a str>>(input: &'a str) -> IResult<&'a str, &'a str, E> {
+    let (mut rest, _) = tag("/*")(input)?;
+    let mut depth = 1;
+    while depth > 0 {
+        if let Some(after) = rest.strip_prefix("*/") {
+            depth -= 1;
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("/*") {
+            depth += 1;
+            rest = after;
+        } else {
+            let mut chars = rest.chars();
+            if chars.next().is_none() {
+                return Err(nom::Err::Failure(E::from_error_kind(input, ErrorKind::Tag)));
+            }
+            rest = chars.as_str();
+        }
+    }
+    Ok((rest, &input[..input.len() - rest.len()]))
+}
+
+/// Skips whitespace, `#` comments (which run to the end of the line) and block comments.
+fn whitespace<'a, E: ParseError<&'a str>>(input: &'a str) -> IResult<&'a str, &'a str, E> {
+    recognize(many0_count(alt((
+        multispace1,
+        recognize(pair(char('#'), not_line_ending)),
+        block_comment,
+    ))))(input)
 }
 
 fn with_whitespace<'a, O, E: ParseError<&'a str>, F: Parser<&'a str, O, E>>(
     f: F,
 ) -> impl Parser<&'a str, O, E> {
-    delimited(multispace0, f, multispace0)
+    delimited(whitespace, f, whitespace)
 }
 
+/// A span for `token` at the start of `input`, counting back from the end of the input until
+/// [`Expression::resolve_spans`] can place it in the whole source.
+fn unresolved_span(input: &str, token: &str) -> Span {
+    Span {
+        start: input.len(),
+        end: input.len() - token.len(),
+        line: 0,
+        column: 0,
+    }
+}
+
+/// Whether `c` can continue a name: a letter or digit in any script, or `_`.
+pub fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// A letter followed by any letters, digits or underscores, in any script, so `α`, `x2` and
+/// `round_to` are names.
 fn parse_name(input: &str) -> IResult<&str, &str> {
-    with_whitespace(alpha1).parse(input)
+    with_whitespace(recognize(pair(
+        satisfy(char::is_alphabetic),
+        many0_count(satisfy(is_name_char)),
+    )))
+    .parse(input)
+}
+
+/// `$1`, `$2` and so on name the positional arguments given after `-e` on the command line, and
+/// `$#` is how many there are.
+fn parse_positional(input: &str) -> IResult<&str, &str> {
+    recognize(preceded(char('$'), alt((digit1, tag("#"))))).parse(input)
 }
 
 fn parse_typ(input: &str) -> IResult<&str, Type> {
-    parse_name(input).map(|(input, name)| (input, Type::Named(name.to_string())))
+    let product = delimited(
+        with_whitespace(char('(')),
+        separated_list0(with_whitespace(char(',')), parse_typ),
+        with_whitespace(char(')')),
+    )
+    .map(Type::Product);
+    parse_name
+        .map(|name| Type::Named(name.to_string()))
+        .or(product)
+        .parse(input)
 }
 
 fn parse_function_type_declaration(input: &str) -> IResult<&str, TopLevelStatement> {
@@ -76,132 +818,892 @@ fn parse_function_type_declaration(input: &str) -> IResult<&str, TopLevelStateme
     ))
 }
 
-fn left_associative_operator_parser<'a, Error: ParseError<&'a str>>(
-    operator: &str,
-    parameter_parser: &mut impl Parser<&'a str, Expression, Error>,
-    operator_constructor: impl Fn(Expression, Expression) -> Expression,
-    input: &'a str,
-) -> IResult<&'a str, Expression, Error> {
-    let mut op_parser = with_whitespace(tag(operator));
-    let (input, first) = parameter_parser.parse(input)?;
-    fold_many1(
-        move |input| {
-            let (input, _) = op_parser.parse(input)?;
-            parameter_parser.parse(input)
+/// Parses an integer, or a decimal with a fractional part and/or exponent such as `2.5e-3`.
+/// An integer too large for an `i64` becomes a decimal.
+/// A `%` written directly after the number, as in `50%`, divides it by 100; a `%` separated by
+/// whitespace is left for an operator.
+fn parse_number(input: &str) -> IResult<&str, Expression> {
+    let (input, literal) = recognize(tuple((
+        digit1,
+        opt(pair(char('.'), digit1)),
+        opt(tuple((one_of("eE"), opt(one_of("+-")), digit1))),
+    )))(input)?;
+    let (input, percent) = opt(char('%'))(input)?;
+    if percent.is_some() {
+        map_res(
+            |input| Ok((input, literal)),
+            |literal: &str| {
+                DBig::from_str(literal).map(|value| {
+                    let repr = value.repr();
+                    Expression::Real(DBig::from_parts(
+                        repr.significand().clone(),
+                        repr.exponent() - 2,
+                    ))
+                })
+            },
+        )(input)
+    } else if literal.contains(['.', 'e', 'E']) {
+        map_res(
+            |input| Ok((input, literal)),
+            |literal: &str| DBig::from_str(literal).map(Expression::Real),
+        )(input)
+    } else {
+        map_res(
+            |input| Ok((input, literal)),
+            |literal: &str| match literal.parse::<i64>() {
+                Ok(integer) => Ok(Expression::Integer(integer)),
+                Err(_) => DBig::from_str(literal).map(Expression::Real),
+            },
+        )(input)
+    }
+}
+
+/// Parses a double-quoted string, which may contain the escapes `\"`, `\\`, `\n` and `\t`.
+fn parse_string(input: &str) -> IResult<&str, Expression> {
+    delimited(
+        char('"'),
+        opt(escaped_transform(
+            none_of("\"\\"),
+            '\\',
+            alt((
+                value('"', char('"')),
+                value('\\', char('\\')),
+                value('\n', char('n')),
+                value('\t', char('t')),
+            )),
+        )),
+        char('"'),
+    )
+    .map(|value| Expression::Str(value.unwrap_or_default()))
+    .parse(input)
+}
+
+/// Parses a raw string such as `r"C:\path"`, in which a backslash is just a backslash. To hold
+/// a quote, open it with `r#"` and close it with `"#`; any number of `#`s may be used, as long as
+/// the closing quote is followed by as many as the opening one.
+fn parse_raw_string(input: &str) -> IResult<&str, Expression> {
+    let (input, hashes) = delimited(char('r'), many0_count(char('#')), char('"'))(input)?;
+    let terminator = format!("\"{}", "#".repeat(hashes));
+    let (input, contents) =
+        terminated(take_until(terminator.as_str()), tag(terminator.as_str()))(input)?;
+    Ok((input, Expression::Str(contents.to_string())))
+}
+
+/// Wraps `operand` in `count` negations.
+fn negate(count: usize, operand: Expression) -> Expression {
+    (0..count).fold(operand, |operand, _| Expression::Negate(Box::new(operand)))
+}
+
+/// Parses a word operator such as `and`, which must not run on into a longer name.
+fn keyword<'a>(word: &'static str) -> impl Parser<&'a str, &'a str, nom::error::Error<&'a str>> {
+    with_whitespace(terminated(tag(word), not(satisfy(is_name_char))))
+}
+
+/// A parameter named this takes its argument without binding it, so there can be several.
+/// In an expression it is an ordinary variable, which the REPL binds to the last result.
+pub const IGNORED_PARAMETER: &str = "_";
+
+fn parse_parameter(input: &str) -> IResult<&str, &str> {
+    alt((parse_name, with_whitespace(tag(IGNORED_PARAMETER)))).parse(input)
+}
+
+/// A name to bind, or a tuple of them to take apart, as in `swap((a, b)) = (b, a)`.
+#[derive(Debug, Clone)]
+enum Destructure {
+    Name(String),
+    Tuple(Vec<Destructure>),
+}
+
+impl Display for Destructure {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Destructure::Name(name) => write!(f, "{}", name),
+            Destructure::Tuple(elements) => {
+                write!(f, "(")?;
+                for (index, element) in elements.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl Destructure {
+    /// The name this is bound to. A tuple is bound under its own text, which can't be written
+    /// as a name in source, and then taken apart by the bindings added to `bindings`.
+    fn bind(self, bindings: &mut Vec<(String, Expression)>) -> String {
+        let name = self.to_string();
+        if let Destructure::Tuple(elements) = self {
+            for (index, element) in elements.into_iter().enumerate() {
+                let value = Expression::Index {
+                    list: Box::new(Expression::Variable {
+                        name: name.clone(),
+                        span: None,
+                    }),
+                    index: Box::new(Expression::Integer(index as i64)),
+                };
+                let mut element_bindings = Vec::new();
+                let element_name = element.bind(&mut element_bindings);
+                if element_name != IGNORED_PARAMETER {
+                    bindings.push((element_name, value));
+                }
+                bindings.extend(element_bindings);
+            }
+        }
+        name
+    }
+}
+
+fn parse_destructure(input: &str) -> IResult<&str, Destructure> {
+    alt((
+        parse_parameter.map(|name| Destructure::Name(name.to_string())),
+        delimited(
+            with_whitespace(char('(')),
+            separated_list1(with_whitespace(char(',')), parse_destructure),
+            with_whitespace(char(')')),
+        )
+        .map(Destructure::Tuple),
+    ))
+    .parse(input)
+}
+
+fn parse_lambda_parameter(input: &str) -> IResult<&str, (String, Option<Expression>)> {
+    let (input, name) = parse_parameter(input)?;
+    let (input, guard) = opt(preceded(keyword("where"), parse_expression))(input)?;
+    Ok((input, (name.to_string(), guard)))
+}
+
+fn parse_lambda(input: &str) -> IResult<&str, Expression> {
+    let (input, _) = with_whitespace(char('(')).parse(input)?;
+    let (input, parameters) =
+        separated_list0(with_whitespace(char(',')), parse_lambda_parameter)(input)?;
+    let (input, _) = with_whitespace(char(')')).parse(input)?;
+    let (input, _) = with_whitespace(tag("=>")).parse(input)?;
+    let (input, body) = parse_expression(input)?;
+    let mut parameter_names = Vec::new();
+    let mut guards = Vec::new();
+    for (name, guard) in parameters {
+        if let Some(guard) = guard {
+            guards.push((name.clone(), guard));
+        }
+        parameter_names.push(name);
+    }
+    Ok((
+        input,
+        Expression::Lambda {
+            parameters: parameter_names,
+            guards,
+            body: Box::new(body),
         },
-        move || first.clone(),
-        |lhs, rhs| operator_constructor(lhs, rhs),
+    ))
+}
+
+/// `name = value`, as in a block or a `where` clause, or `(a, b) = value` to take apart a
+/// tuple, which binds the tuple and then each of its elements.
+fn parse_binding(input: &str) -> IResult<&str, Vec<(String, Expression)>> {
+    let target = alt((
+        parse_name.map(|name| Destructure::Name(name.to_string())),
+        parse_destructure,
+    ));
+    pair(
+        target,
+        preceded(
+            with_whitespace(terminated(char('='), not(char('=')))),
+            parse_expression,
+        ),
     )
+    .map(|(target, value)| {
+        let mut elements = Vec::new();
+        let name = target.bind(&mut elements);
+        let mut bindings = vec![(name, value)];
+        bindings.extend(elements);
+        bindings
+    })
     .parse(input)
 }
 
-pub fn parse_expression(input: &str) -> IResult<&str, Expression> {
-    let integer_parser = with_whitespace(map_res(digit1, |s: &str| {
-        s.parse::<i64>().map(Expression::Integer)
-    }));
-    let variable_parser = with_whitespace(parse_name).map(|s| Expression::Variable(s.to_string()));
-    let bracketed_expression = with_whitespace(delimited(tag("("), parse_expression, tag(")")));
+fn parse_block(input: &str) -> IResult<&str, Expression> {
+    let (input, _) = with_whitespace(char('{')).parse(input)?;
+    let (input, bindings) = many0(terminated(parse_binding, with_whitespace(char(';'))))(input)?;
+    let bindings = bindings.into_iter().flatten().collect();
+    let (input, result) = parse_expression(input)?;
+    let (input, _) = with_whitespace(char('}')).parse(input)?;
+    Ok((
+        input,
+        Expression::Block {
+            bindings,
+            result: Box::new(result),
+        },
+    ))
+}
 
-    let negative_expression = with_whitespace(char('-'))
-        .and(parse_expression)
-        .map(|(_, expression)| Expression::Negate(Box::new(expression)));
+fn parse_pattern(input: &str) -> IResult<&str, Pattern> {
+    let integer =
+        map_res(recognize(pair(opt(char('-')), digit1)), str::parse).map(Pattern::Integer);
+    let wildcard = value(
+        Pattern::Wildcard,
+        terminated(tag(IGNORED_PARAMETER), not(satisfy(is_name_char))),
+    );
+    with_whitespace(alt((integer, wildcard))).parse(input)
+}
 
-    let mut atomic_expression = integer_parser.or(variable_parser).or(bracketed_expression).or(negative_expression);
+/// `match scrutinee { pattern => result, ... }`, with an optional trailing comma.
+fn parse_match(input: &str) -> IResult<&str, Expression> {
+    let arm = pair(
+        parse_pattern,
+        preceded(with_whitespace(tag("=>")), parse_expression),
+    );
+    let (input, _) = keyword("match").parse(input)?;
+    let (input, scrutinee) = parse_expression(input)?;
+    let (input, _) = with_whitespace(char('{')).parse(input)?;
+    let (input, arms) = separated_list1(with_whitespace(char(',')), arm)(input)?;
+    let (input, _) = opt(with_whitespace(char(',')))(input)?;
+    let (input, _) = with_whitespace(char('}')).parse(input)?;
+    Ok((
+        input,
+        Expression::Match {
+            scrutinee: Box::new(scrutinee),
+            arms,
+        },
+    ))
+}
 
-    let mut possibly_apply_parser = move |input| {
-        let (input, first) = atomic_expression.parse(input)?;
-        with_whitespace(char('('))
-            .and(separated_list0(
-                with_whitespace(char(',')),
-                parse_expression,
-            ))
-            .and(with_whitespace(char(')')))
-            .map(|((_, arguments), _)| Expression::ApplyFunction {
-                function: Box::new(first.clone()),
-                arguments,
-            })
-            .parse(input)
-            .or_else(|_| Ok((input, first)))
+pub fn parse_expression(input: &str) -> IResult<&str, Expression> {
+    parse_expression_with_bit_or(input, true)
+}
+
+/// The contents of `|...|` can't use `|` as bitwise or without brackets, so that a `|` there
+/// always closes the bars.
+fn parse_bar_contents(input: &str) -> IResult<&str, Expression> {
+    parse_expression_with_bit_or(input, false)
+}
+
+/// An operand of the binary operators: anything bound more tightly than `*`, `/` and `//`.
+fn parse_operand(input: &str) -> IResult<&str, Expression> {
+    let number_parser = with_whitespace(parse_number);
+    let string_parser = with_whitespace(alt((parse_raw_string, parse_string)));
+    let variable_parser = |input| {
+        let (input, _) = whitespace(input)?;
+        let (rest, name) = alt((parse_parameter, parse_positional))(input)?;
+        Ok((
+            rest,
+            match name {
+                "true" => Expression::Bool(true),
+                "false" => Expression::Bool(false),
+                _ => Expression::Variable {
+                    name: name.to_string(),
+                    span: Some(unresolved_span(input, name)),
+                },
+            },
+        ))
+    };
+    // A comma makes brackets a tuple, so `(a)` is just `a` but `(a,)` and `(a, b)` are tuples.
+    let bracketed_expression = with_whitespace(delimited(
+        char('('),
+        pair(
+            separated_list1(with_whitespace(char(',')), parse_expression),
+            opt(with_whitespace(char(','))),
+        ),
+        with_whitespace(char(')')),
+    ))
+    .map(|(mut elements, trailing_comma)| {
+        if elements.len() == 1 && trailing_comma.is_none() {
+            elements.pop().unwrap()
+        } else {
+            Expression::Tuple(elements)
+        }
+    });
+    // `(+)` and the like name the native function that does what the operator does.
+    let operator_section = |input| {
+        let (input, _) = whitespace(input)?;
+        let (rest, section) = recognize(tuple((
+            char('('),
+            with_whitespace(one_of("+-*/^")),
+            with_whitespace(char(')')),
+        )))(input)?;
+        let operator: String = section.chars().filter(|c| !c.is_whitespace()).collect();
+        Ok((
+            rest,
+            Expression::Variable {
+                span: Some(unresolved_span(input, section)),
+                name: operator,
+            },
+        ))
     };
+    let list_expression = with_whitespace(delimited(
+        char('['),
+        separated_list0(with_whitespace(char(',')), parse_expression),
+        with_whitespace(char(']')),
+    ))
+    .map(Expression::List);
+    let abs_expression = with_whitespace(delimited(
+        char('|'),
+        parse_bar_contents,
+        with_whitespace(char('|')),
+    ))
+    .map(|operand| Expression::Abs(Box::new(operand)));
 
-    // TODO: switch to a right-associative parser
-    let mut possibly_power_parser = move |input| {
-        left_associative_operator_parser(
-            "^",
-            &mut possibly_apply_parser,
-            |lhs, rhs| Expression::Power(Box::new(lhs), Box::new(rhs)),
-            input,
+    let mut atomic_expression = number_parser
+        .or(string_parser)
+        .or(parse_match)
+        .or(variable_parser)
+        .or(parse_lambda)
+        .or(operator_section)
+        .or(bracketed_expression)
+        .or(list_expression)
+        .or(abs_expression)
+        .or(parse_block);
+
+    // Calls and indexing group to the left and can be chained in any order, so `f(x)(y)` calls
+    // the result of `f(x)` and `fs[0](x)` calls an element of a list.
+    enum Postfix {
+        Call(Vec<Expression>),
+        Index(Expression),
+    }
+    let mut possibly_postfix_parser = move |input| {
+        let (input, first) = atomic_expression.parse(input)?;
+        let call = delimited(
+            with_whitespace(char('(')),
+            separated_list0(with_whitespace(char(',')), parse_expression),
+            with_whitespace(char(')')),
         )
-        .or_else(|_| possibly_apply_parser.parse(input))
+        .map(Postfix::Call);
+        let index = with_whitespace(delimited(
+            char('['),
+            parse_expression,
+            with_whitespace(char(']')),
+        ))
+        .map(Postfix::Index);
+        fold_many0(
+            alt((call, index)),
+            move || first.clone(),
+            |operand, postfix| match postfix {
+                Postfix::Call(arguments) => Expression::ApplyFunction {
+                    function: Box::new(operand),
+                    arguments,
+                },
+                Postfix::Index(index) => Expression::Index {
+                    list: Box::new(operand),
+                    index: Box::new(index),
+                },
+            },
+        )
+        .parse(input)
     };
 
-    let mut possibly_divide_parser = move |input| {
-        left_associative_operator_parser(
-            "/",
-            &mut possibly_power_parser,
-            |lhs, rhs| Expression::Divide(Box::new(lhs), Box::new(rhs)),
-            input,
+    let mut possibly_factorial_parser = move |input| {
+        let (input, first) = possibly_postfix_parser(input)?;
+        fold_many0(
+            with_whitespace(terminated(char('!'), not(char('=')))),
+            move || first.clone(),
+            |operand, _| Expression::Factorial(Box::new(operand)),
         )
-        .or_else(|_| possibly_power_parser(input))
+        .parse(input)
     };
 
-    let mut possibly_multiply_parser = move |input| {
-        left_associative_operator_parser(
-            "*",
-            &mut possibly_divide_parser,
-            |lhs, rhs| Expression::Multiply(Box::new(lhs), Box::new(rhs)),
+    // Exponentiation groups to the right, so `2^3^2` is `2^(3^2)`. `**` is accepted as well as `^`.
+    // Unary minus binds more loosely than `^` but applies to everything to its right, so `-2^2` is
+    // `-(2^2)` while `2^-1` still parses.
+    let mut possibly_power_parser = move |input| {
+        let mut signed_operand = pair(
+            many0_count(with_whitespace(char('-'))),
+            &mut possibly_factorial_parser,
+        );
+        let (input, first) = signed_operand.parse(input)?;
+        let (input, mut operands) = many0(preceded(
+            with_whitespace(alt((tag("^"), tag("**")))),
+            &mut signed_operand,
+        ))(input)?;
+        operands.insert(0, first);
+        let (signs, last) = operands.pop().unwrap();
+        Ok((
             input,
-        )
-        .or_else(|_| possibly_divide_parser(input))
+            operands
+                .into_iter()
+                .rev()
+                .fold(negate(signs, last), |rhs, (signs, lhs)| {
+                    negate(signs, Expression::Power(Box::new(lhs), Box::new(rhs)))
+                }),
+        ))
     };
 
-    let mut possibly_subtract_parser = move |input| {
-        left_associative_operator_parser(
-            "-",
-            &mut possibly_multiply_parser,
-            |lhs, rhs| Expression::Subtract(Box::new(lhs), Box::new(rhs)),
-            input,
+    // A number written directly against a name or bracket, as in `2x` or `3(x+1)`, multiplies it.
+    // A leading minus negates the product, so `-2x` is `-(2x)`.
+    let mut possibly_implicit_multiply_parser = move |input| {
+        let coefficient = pair(
+            many0_count(with_whitespace(char('-'))),
+            delimited(
+                whitespace,
+                parse_number,
+                peek(satisfy(|c| c.is_alphabetic() || c == '(')),
+            ),
         )
-        .or_else(|_| possibly_multiply_parser(input))
+        .parse(input);
+        match coefficient {
+            Ok((input, (signs, coefficient))) => {
+                let (input, rhs) = possibly_power_parser(input)?;
+                Ok((
+                    input,
+                    negate(
+                        signs,
+                        Expression::Multiply(Box::new(coefficient), Box::new(rhs)),
+                    ),
+                ))
+            }
+            Err(_) => possibly_power_parser(input),
+        }
     };
 
-    let mut possibly_add_parser = move |input| {
-        left_associative_operator_parser(
-            "+",
-            &mut possibly_subtract_parser,
-            |lhs, rhs| Expression::Add(Box::new(lhs), Box::new(rhs)),
+    possibly_implicit_multiply_parser.parse(input)
+}
+
+type Constructor = fn(Expression, Expression) -> Expression;
+
+/// How a run of operators from the same level of the precedence table combine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Associativity {
+    /// `a - b - c` is `(a - b) - c`.
+    Left,
+    /// Chained comparisons such as `1 < x <= 10` become a conjunction of the individual
+    /// comparisons. A middle operand that isn't a plain name or literal is bound once in a block,
+    /// under a name that can't be written in source, so that it is only evaluated once.
+    Chained,
+}
+
+/// One level of the precedence table. An operator spelt with letters, such as `and`, is a
+/// keyword and so can't run on into a longer name.
+struct Level {
+    operators: &'static [(&'static str, Constructor)],
+    associativity: Associativity,
+}
+
+/// The binary operators from the loosest to the tightest. The arithmetic operators group to the
+/// left, so `a - b + c` is `(a - b) + c` and `7 * 3 // 2` is `(7 * 3) // 2`.
+/// Bitwise operators bind more loosely than comparisons: shifts, then `&`, `xor` and `|`.
+const PRECEDENCE: &[Level] = &[
+    Level {
+        operators: &[("and", |lhs, rhs| {
+            Expression::And(Box::new(lhs), Box::new(rhs))
+        })],
+        associativity: Associativity::Left,
+    },
+    Level {
+        operators: &[("|", |lhs, rhs| {
+            Expression::BitOr(Box::new(lhs), Box::new(rhs))
+        })],
+        associativity: Associativity::Left,
+    },
+    Level {
+        operators: &[("xor", |lhs, rhs| {
+            Expression::BitXor(Box::new(lhs), Box::new(rhs))
+        })],
+        associativity: Associativity::Left,
+    },
+    Level {
+        operators: &[("&", |lhs, rhs| {
+            Expression::BitAnd(Box::new(lhs), Box::new(rhs))
+        })],
+        associativity: Associativity::Left,
+    },
+    Level {
+        operators: &[
+            ("<<", |lhs, rhs| {
+                Expression::ShiftLeft(Box::new(lhs), Box::new(rhs))
+            }),
+            (">>", |lhs, rhs| {
+                Expression::ShiftRight(Box::new(lhs), Box::new(rhs))
+            }),
+        ],
+        associativity: Associativity::Left,
+    },
+    Level {
+        operators: &[
+            ("==", |lhs, rhs| {
+                Expression::Equal(Box::new(lhs), Box::new(rhs))
+            }),
+            ("!=", |lhs, rhs| {
+                Expression::NotEqual(Box::new(lhs), Box::new(rhs))
+            }),
+            ("<=", |lhs, rhs| {
+                Expression::LessThanOrEqual(Box::new(lhs), Box::new(rhs))
+            }),
+            (">=", |lhs, rhs| {
+                Expression::GreaterThanOrEqual(Box::new(lhs), Box::new(rhs))
+            }),
+            ("<", |lhs, rhs| {
+                Expression::LessThan(Box::new(lhs), Box::new(rhs))
+            }),
+            (">", |lhs, rhs| {
+                Expression::GreaterThan(Box::new(lhs), Box::new(rhs))
+            }),
+        ],
+        associativity: Associativity::Chained,
+    },
+    Level {
+        operators: &[
+            ("+", |lhs, rhs| {
+                Expression::Add(Box::new(lhs), Box::new(rhs))
+            }),
+            ("-", |lhs, rhs| {
+                Expression::Subtract(Box::new(lhs), Box::new(rhs))
+            }),
+        ],
+        associativity: Associativity::Left,
+    },
+    // `//` comes before `/` so that it isn't read as two divisions.
+    Level {
+        operators: &[
+            ("*", |lhs, rhs| {
+                Expression::Multiply(Box::new(lhs), Box::new(rhs))
+            }),
+            ("//", |lhs, rhs| {
+                Expression::IntegerDivide(Box::new(lhs), Box::new(rhs))
+            }),
+            ("/", |lhs, rhs| {
+                Expression::Divide(Box::new(lhs), Box::new(rhs))
+            }),
+        ],
+        associativity: Associativity::Left,
+    },
+];
+
+/// Parses operands joined by the operators of `levels`, the first of which binds most loosely.
+/// Without `bit_or`, `|` isn't an operator, as in the contents of `|...|`.
+fn parse_binary<'a>(
+    levels: &[Level],
+    bit_or: bool,
+    input: &'a str,
+) -> IResult<&'a str, Expression> {
+    let Some((level, tighter)) = levels.split_first() else {
+        return parse_operand(input);
+    };
+    let operator = |input| {
+        for &(symbol, constructor) in level.operators {
+            if symbol == "|" && !bit_or {
+                continue;
+            }
+            let token = if symbol.starts_with(char::is_alphabetic) {
+                keyword(symbol).parse(input)
+            } else {
+                with_whitespace(tag(symbol)).parse(input)
+            };
+            if let Ok((input, _)) = token {
+                return Ok((input, constructor));
+            }
+        }
+        Err(nom::Err::Error(nom::error::Error::new(
             input,
-        )
-        .or_else(|_| possibly_subtract_parser(input))
+            ErrorKind::Tag,
+        )))
     };
+    let (input, first) = parse_binary(tighter, bit_or, input)?;
+    let (input, rest) = many0(pair(operator, |input| parse_binary(tighter, bit_or, input)))(input)?;
+    if rest.is_empty() {
+        return Ok((input, first));
+    }
+    match level.associativity {
+        Associativity::Left => Ok((
+            input,
+            rest.into_iter()
+                .fold(first, |lhs, (constructor, rhs)| constructor(lhs, rhs)),
+        )),
+        Associativity::Chained => {
+            let mut operands = vec![first];
+            let mut constructors = Vec::new();
+            for (constructor, operand) in rest {
+                constructors.push(constructor);
+                operands.push(operand);
+            }
+            let mut bindings = Vec::new();
+            let last = operands.len() - 1;
+            for (index, operand) in operands.iter_mut().enumerate() {
+                let simple = matches!(
+                    operand,
+                    Expression::Integer(_)
+                        | Expression::Real(_)
+                        | Expression::Bool(_)
+                        | Expression::Str(_)
+                        | Expression::Variable { .. }
+                );
+                if index > 0 && index < last && !simple {
+                    let name = format!("#{}", bindings.len());
+                    let variable = Expression::Variable {
+                        name: name.clone(),
+                        span: None,
+                    };
+                    let value = std::mem::replace(operand, variable);
+                    bindings.push((name, value));
+                }
+            }
+            let conjunction = constructors
+                .into_iter()
+                .zip(operands.windows(2))
+                .map(|(constructor, pair)| constructor(pair[0].clone(), pair[1].clone()))
+                .reduce(|lhs, rhs| Expression::And(Box::new(lhs), Box::new(rhs)))
+                .unwrap();
+            Ok((
+                input,
+                if bindings.is_empty() {
+                    conjunction
+                } else {
+                    Expression::Block {
+                        bindings,
+                        result: Box::new(conjunction),
+                    }
+                },
+            ))
+        }
+    }
+}
 
-    possibly_add_parser.parse(input)
+fn parse_expression_with_bit_or(input: &str, bit_or: bool) -> IResult<&str, Expression> {
+    // `condition ? a : b`, the loosest construct; the branches nest to the right.
+    let (input, condition) = parse_binary(PRECEDENCE, bit_or, input)?;
+    let (input, branches) = opt(pair(
+        preceded(with_whitespace(char('?')), parse_expression),
+        preceded(with_whitespace(char(':')), parse_expression),
+    ))(input)?;
+    Ok((
+        input,
+        match branches {
+            Some((then, otherwise)) => Expression::If {
+                condition: Box::new(condition),
+                then: Box::new(then),
+                otherwise: Box::new(otherwise),
+            },
+            None => condition,
+        },
+    ))
+}
+
+/// Continues a function body with any guards, as in `x if x >= 0 | -x otherwise`. Conditions
+/// and later branches can't use `|` as bitwise or without brackets, since it separates branches.
+fn parse_guards(first: Expression, input: &str) -> IResult<&str, Expression> {
+    let mut guard = preceded(keyword("if"), parse_bar_contents);
+    let (mut input, condition) = match guard.parse(input) {
+        Ok(parsed) => parsed,
+        Err(nom::Err::Error(_)) => return Ok((input, first)),
+        Err(e) => return Err(e),
+    };
+    let mut branches = vec![(condition, first)];
+    let mut otherwise = None;
+    while let Ok((rest, _)) = with_whitespace(char::<_, nom::error::Error<&str>>('|')).parse(input)
+    {
+        let (rest, result) = parse_bar_contents(rest)?;
+        if let Ok((rest, _)) = keyword("otherwise").parse(rest) {
+            input = rest;
+            otherwise = Some(Box::new(result));
+            break;
+        }
+        let (rest, condition) = guard.parse(rest)?;
+        input = rest;
+        branches.push((condition, result));
+    }
+    Ok((
+        input,
+        Expression::Guarded {
+            branches,
+            otherwise,
+        },
+    ))
 }
 
 pub fn parse_function_definition(input: &str) -> IResult<&str, TopLevelStatement> {
     let (input, name) = parse_name(input)?;
     let (input, _) = with_whitespace(tag("(")).parse(input)?;
-    let (input, parameters) = separated_list0(
-        with_whitespace(tag(",")),
-        parse_name.map(|name| name.to_string()),
-    )(input)?;
+    let (input, parameters) = separated_list0(with_whitespace(tag(",")), parse_destructure)(input)?;
     let (input, _) = with_whitespace(tag(")")).parse(input)?;
     let (input, _) = with_whitespace(tag("=")).parse(input)?;
     let (input, body) = parse_expression(input)?;
+    let (input, body) = parse_guards(body, input)?;
+    let (input, local_bindings) = opt(preceded(
+        keyword("where"),
+        separated_list1(with_whitespace(char(';')), parse_binding),
+    ))(input)?;
+    // Tuple parameters are taken apart before the `where` clause, so it can use their elements.
+    let mut bindings = Vec::new();
+    let parameters = parameters
+        .into_iter()
+        .map(|parameter| parameter.bind(&mut bindings))
+        .collect();
+    bindings.extend(local_bindings.into_iter().flatten().flatten());
     Ok((
         input,
         TopLevelStatement::FunctionDefinition {
             name: name.to_string(),
             parameters,
             body,
+            bindings,
         },
     ))
 }
 
-pub fn parse_top_level(input: &str) -> IResult<&str, Vec<TopLevelStatement>> {
-    many0(alt((
-        parse_function_type_declaration,
-        parse_function_definition,
-    )))
-    .parse(input)
+pub fn parse_variable_definition(input: &str) -> IResult<&str, TopLevelStatement> {
+    let (input, name) = parse_name(input)?;
+    let (input, _) = with_whitespace(tag("=")).parse(input)?;
+    let (input, value) = parse_expression(input)?;
+    Ok((
+        input,
+        TopLevelStatement::VariableDefinition {
+            name: name.to_string(),
+            value,
+        },
+    ))
+}
+
+impl TopLevelStatement {
+    fn resolve_spans(&mut self, source: &str) {
+        match self {
+            TopLevelStatement::FunctionDefinition { body, bindings, .. } => {
+                for (_, value) in bindings {
+                    value.resolve_spans(source);
+                }
+                body.resolve_spans(source)
+            }
+            TopLevelStatement::VariableDefinition { value, .. }
+            | TopLevelStatement::Expression(value) => value.resolve_spans(source),
+            TopLevelStatement::FunctionTypeDeclaration { .. } => {}
+        }
+    }
+}
+
+/// Parses an entire program, failing if any of the source is left over.
+pub fn parse_program(source: &str) -> Result<Vec<TopLevelStatement>, SyntaxError> {
+    let (top_level, errors) = parse_program_with_errors(source);
+    match errors.into_iter().next() {
+        Some(error) => Err(error),
+        None => Ok(top_level),
+    }
+}
+
+/// Parses as much of a program as possible: after each syntax error, parsing resumes on the
+/// next line. Returns the statements that parsed along with every error.
+pub fn parse_program_with_errors(source: &str) -> (Vec<TopLevelStatement>, Vec<SyntaxError>) {
+    let mut top_level = Vec::new();
+    let mut errors = Vec::new();
+    for parsed in Statements::new(source) {
+        match parsed {
+            Ok(statement) => top_level.push(statement),
+            Err(error) => errors.push(error),
+        }
+    }
+    (top_level, errors)
+}
+
+/// The statements of a program, parsed one at a time as they are asked for. After a syntax
+/// error, parsing resumes on the next line.
+pub struct Statements<'a> {
+    source: &'a str,
+    remaining: &'a str,
+    expressions: bool,
+}
+
+impl<'a> Statements<'a> {
+    pub fn new(source: &'a str) -> Statements<'a> {
+        let remaining = match source.strip_prefix("#!") {
+            Some(rest) => rest.find('\n').map_or("", |newline| &rest[newline..]),
+            None => source,
+        };
+        Statements {
+            source,
+            remaining,
+            expressions: false,
+        }
+    }
+
+    /// Like [`Statements::new`], but also accepts bare expressions between the definitions,
+    /// as [`TopLevelStatement::Expression`].
+    pub fn with_expressions(source: &'a str) -> Statements<'a> {
+        Statements {
+            expressions: true,
+            ..Statements::new(source)
+        }
+    }
+}
+
+impl<'a> Iterator for Statements<'a> {
+    type Item = Result<TopLevelStatement, SyntaxError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (rest, _) = whitespace::<nom::error::Error<&str>>(self.remaining).ok()?;
+        if rest.is_empty() {
+            return None;
+        }
+        let statement_parsers = [
+            parse_function_type_declaration,
+            parse_function_definition,
+            parse_variable_definition,
+        ];
+        let mut furthest = rest;
+        for parser in statement_parsers {
+            match parser(rest) {
+                Ok((rest, mut statement)) => {
+                    self.remaining = rest;
+                    statement.resolve_spans(self.source);
+                    return Some(Ok(statement));
+                }
+                // Report wherever the statement that got furthest gave up, rather than where
+                // it started.
+                Err(nom::Err::Error(e) | nom::Err::Failure(e))
+                    if e.input.len() < furthest.len() =>
+                {
+                    furthest = e.input
+                }
+                Err(_) => {}
+            }
+        }
+        if self.expressions {
+            // A definition that got further than the expression would is a broken definition,
+            // as in `z = )`, rather than the expression `z` followed by junk.
+            match parse_expression(rest) {
+                Ok((rest, mut expression)) if rest.len() <= furthest.len() => {
+                    self.remaining = rest;
+                    expression.resolve_spans(self.source);
+                    return Some(Ok(TopLevelStatement::Expression(expression)));
+                }
+                Err(nom::Err::Error(e) | nom::Err::Failure(e))
+                    if e.input.len() < furthest.len() =>
+                {
+                    furthest = e.input
+                }
+                _ => {}
+            }
+        }
+        self.remaining = furthest
+            .find('\n')
+            .map_or("", |newline| &furthest[newline + 1..]);
+        Some(Err(SyntaxError::at(self.source, furthest)))
+    }
+}
+
+/// Parses a single expression, failing if any of the source is left over.
+pub fn parse_complete_expression(source: &str) -> Result<Expression, SyntaxError> {
+    let (remaining, mut expression) =
+        parse_expression(source).map_err(|e| SyntaxError::from_nom(source, e))?;
+    if remaining.trim().is_empty() {
+        expression.resolve_spans(source);
+        Ok(expression)
+    } else {
+        Err(SyntaxError::at(source, remaining))
+    }
+}
+
+/// Parses a single top-level statement (a definition or a type declaration) the way a program
+/// is parsed, failing if any of the source is left over.
+pub fn parse_complete_definition(source: &str) -> Result<TopLevelStatement, SyntaxError> {
+    let mut statements = Statements::new(source);
+    let statement = statements
+        .next()
+        .unwrap_or_else(|| Err(SyntaxError::at(source, "")))?;
+    let (remaining, _) = whitespace::<nom::error::Error<&str>>(statements.remaining)
+        .map_err(|e| SyntaxError::from_nom(source, e))?;
+    if remaining.is_empty() {
+        Ok(statement)
+    } else {
+        Err(SyntaxError::at(source, remaining))
+    }
 }
@@ -1,27 +1,80 @@
-use std::{collections::BTreeMap, fmt::{self, Display, Formatter}};
+use std::{
+    cell::Cell,
+    cmp::Ordering,
+    collections::BTreeMap,
+    fmt::{self, Debug, Display, Formatter},
+    io::Write,
+};
 
-use dashu_float::{round::mode, FBig};
+use dashu_float::{
+    ops::{Abs, SquareRoot},
+    round::mode,
+    DBig, FBig,
+};
+use dashu_int::{ops::UnsignedAbs, IBig};
+use dashu_ratio::RBig;
 
-use crate::parser::Expression;
+use crate::parser::{Expression, Pattern, Span, Type, IGNORED_PARAMETER};
 
 #[derive(Debug, Clone)]
 pub struct Function {
-    pub name: String,
+    /// The name the function was defined with, or `None` for a lambda.
+    pub name: Option<String>,
     pub parameter_names: Vec<String>,
+    pub guards: Vec<(String, Expression)>,
+    /// Declared types of the leading parameters, checked when arguments are supplied.
+    pub parameter_types: Vec<Type>,
+    /// The declared return type, which results are converted to or checked against.
+    pub return_type: Option<Type>,
     pub body: Expression,
+    /// Local definitions from a `where` clause, evaluated in order before the body.
+    pub bindings: Vec<(String, Expression)>,
+    /// Arguments already supplied by partial application.
+    pub captured: BTreeMap<String, Value>,
+    /// How many arguments partial application has supplied, so that argument positions count
+    /// from the function's first parameter.
+    pub supplied: usize,
+    /// Which of the context's memo tables caches this function's results, once memoized.
+    pub memo_table: Option<usize>,
+}
+
+pub type NativeImplementation =
+    fn(&mut EvalContext, &BTreeMap<String, Value>, &[Value]) -> Result<Value, RuntimeError>;
+
+/// A function implemented in Rust, accepting between `minimum_arguments` and
+/// `maximum_arguments` (unbounded if `None`) already-evaluated arguments. It is given the
+/// caller's context and variables so that it can call back into function arguments.
+#[derive(Debug, Clone)]
+pub struct NativeFunction {
+    pub name: &'static str,
+    /// A sentence saying what the function does, shown by `:help`.
+    pub description: &'static str,
+    pub minimum_arguments: usize,
+    pub maximum_arguments: Option<usize>,
+    pub function: NativeImplementation,
 }
 
 #[derive(Debug, Clone)]
 pub enum Value {
     SmallInt(i64),
     Real(FBig),
+    Rational(RBig),
+    Complex { re: FBig, im: FBig },
+    Bool(bool),
+    Str(String),
+    List(Vec<Value>),
+    Tuple(Vec<Value>),
 
     Function(Function),
+    NativeFunction(NativeFunction),
 }
 
 #[derive(Debug, Clone)]
 pub enum RuntimeError {
-    UnboundVariable(String),
+    UnboundVariable {
+        name: String,
+        span: Option<Span>,
+    },
     InvalidType {
         found: String,
         operation: String,
@@ -36,43 +89,639 @@ pub enum RuntimeError {
         expected: usize,
         found: usize,
     },
+    GuardViolation {
+        parameter: String,
+    },
+    /// An argument that doesn't have its parameter's declared type; `position` counts from 1.
+    ArgumentType {
+        position: usize,
+        parameter: String,
+        expected: String,
+        found: String,
+    },
+    InvalidArgument {
+        function: String,
+        reason: String,
+    },
+    AssertionFailed {
+        message: String,
+    },
+    RecursionLimitExceeded {
+        limit: usize,
+    },
+    /// A result that can't be converted to its function's declared return type.
+    ReturnType {
+        function: String,
+        expected: String,
+        found: String,
+    },
+    IndexOutOfRange {
+        index: i64,
+        length: usize,
+    },
+    /// A guarded function body with no `otherwise` whose conditions were all false.
+    NoMatchingGuard,
+    /// A function definition that names the same parameter twice.
+    DuplicateParameter {
+        function: String,
+        parameter: String,
+    },
+    /// Variables defined in terms of themselves, directly or through each other.
+    CyclicDefinition {
+        names: Vec<String>,
+    },
+    /// A SmallInt result out of range while overflow is an error.
+    IntegerOverflow {
+        operation: String,
+    },
+    /// A `match` with no arm for the value it was given.
+    NonExhaustiveMatch {
+        value: String,
+    },
+    /// A `range` with more elements than the context allows.
+    RangeTooLong {
+        length: u128,
+        limit: usize,
+    },
+}
+
+/// A rounding mode for real arithmetic, in place of dashu's defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    Zero,
+    /// To the nearest value, with ties to even.
+    Nearest,
+    Up,
+    Down,
+}
+
+/// Evaluates `$operation` with each named real operand switched to `$rounding`, or in dashu's
+/// default modes when that is `None`. The result is always back in the usual mode.
+macro_rules! rounded {
+    ($rounding:expr, |$($operand:ident),+| $operation:expr) => {
+        match $rounding {
+            None => {
+                let result: FBig<_> = $operation;
+                result.with_rounding::<mode::Zero>()
+            }
+            Some(Rounding::Zero) => rounded!(@in mode::Zero, $($operand),+; $operation),
+            Some(Rounding::Nearest) => rounded!(@in mode::HalfEven, $($operand),+; $operation),
+            Some(Rounding::Up) => rounded!(@in mode::Up, $($operand),+; $operation),
+            Some(Rounding::Down) => rounded!(@in mode::Down, $($operand),+; $operation),
+        }
+    };
+    (@in $mode:ty, $($operand:ident),+; $operation:expr) => {{
+        $(let $operand = $operand.with_rounding::<$mode>();)+
+        let result: FBig<$mode> = $operation;
+        result.with_rounding::<mode::Zero>()
+    }};
+}
+
+/// What SmallInt arithmetic does when a result is out of range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowMode {
+    /// The result becomes a Decimal.
+    Promote,
+    /// The result wraps around, as in two's complement.
+    Wrap,
+    /// Evaluation fails with [`RuntimeError::IntegerOverflow`].
+    Error,
+}
+
+/// How deeply user functions can call each other by default. Evaluation grows its own stack as
+/// it goes, so reaching the limit is an error rather than a stack overflow, even on a small
+/// thread stack.
+pub const DEFAULT_RECURSION_LIMIT: usize = 1000;
+
+/// How much stack must be left before evaluating another expression: one debug build frame of
+/// `evaluate_untraced` is large, and builtins such as `fold` call back into it.
+const STACK_RED_ZONE: usize = 1 << 20;
+
+/// How much stack to allocate whenever evaluation runs low.
+const STACK_SEGMENT: usize = 16 << 20;
+
+/// How many elements `range` can produce by default.
+pub const DEFAULT_MAX_RANGE_LENGTH: usize = 1_000_000;
+
+/// Where `print` writes: stdout, unless an embedder wants to capture it.
+pub struct Output(pub Box<dyn Write>);
+
+impl Default for Output {
+    fn default() -> Self {
+        Output(Box::new(std::io::stdout()))
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl Debug for Output {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Output")
+    }
+}
+
+#[derive(Debug)]
+pub struct EvalContext {
+    /// Number of calls made to each function by name, while profiling is enabled.
+    pub call_counts: Option<BTreeMap<String, usize>>,
+    pub display: DisplaySettings,
+    /// The rounding mode for reals, or `None` for dashu's defaults.
+    pub rounding: Option<Rounding>,
+    /// What `+`, `-`, `*` and `^` do when a SmallInt result overflows.
+    pub overflow: OverflowMode,
+    /// Results of memoized functions, keyed by their arguments.
+    pub memo_tables: Vec<BTreeMap<MemoKey, Value>>,
+    /// While tracing, how deeply nested the expression being evaluated is.
+    pub trace_depth: Option<usize>,
+    /// The most user function calls that can be in progress at once, unless `None`. The stack
+    /// grows on the heap as needed, so only memory bounds recursion without a limit.
+    pub recursion_limit: Option<usize>,
+    /// How many user function calls are in progress.
+    pub call_depth: usize,
+    /// Where `print` writes.
+    pub output: Output,
+    /// The most elements `range` can produce, unless `None`.
+    pub max_range_length: Option<usize>,
+}
+
+impl Default for EvalContext {
+    fn default() -> Self {
+        EvalContext {
+            call_counts: None,
+            display: DisplaySettings::default(),
+            rounding: None,
+            overflow: OverflowMode::Promote,
+            memo_tables: Vec::new(),
+            trace_depth: None,
+            recursion_limit: Some(DEFAULT_RECURSION_LIMIT),
+            call_depth: 0,
+            output: Output::default(),
+            max_range_length: Some(DEFAULT_MAX_RANGE_LENGTH),
+        }
+    }
+}
+
+const DEFAULT_MAX_REAL_LENGTH: usize = 80;
+const DEFAULT_MAX_LIST_LENGTH: usize = 50;
+
+/// Controls how values are shown to the user; the values themselves are unaffected.
+#[derive(Debug, Clone)]
+pub struct DisplaySettings {
+    /// Decimals longer than this many characters are cut short, unless `None`.
+    pub max_real_length: Option<usize>,
+    /// Lists with more elements than this show only the first ones, unless `None`.
+    pub max_list_length: Option<usize>,
+    /// Decimals are rounded to this many significant digits, unless `None`.
+    pub significant_digits: Option<usize>,
+    /// Decimals whose magnitude falls outside this range use scientific notation, unless `None`.
+    pub scientific: Option<ScientificRange>,
+    /// Decimals that are simple fractions, such as `0.25`, are shown as one, such as `1/4`.
+    pub fractions: bool,
+}
+
+/// Decimal exponents between `min_exponent` and `max_exponent` (exclusive) are written out in
+/// full; anything beyond them is written as, for example, `1.23e10`.
+#[derive(Debug, Clone)]
+pub struct ScientificRange {
+    pub min_exponent: isize,
+    pub max_exponent: isize,
+}
+
+impl Default for ScientificRange {
+    fn default() -> Self {
+        ScientificRange {
+            min_exponent: -5,
+            max_exponent: 10,
+        }
+    }
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        DisplaySettings {
+            max_real_length: Some(DEFAULT_MAX_REAL_LENGTH),
+            max_list_length: Some(DEFAULT_MAX_LIST_LENGTH),
+            significant_digits: None,
+            scientific: None,
+            fractions: false,
+        }
+    }
+}
+
+/// A value paired with the settings to display it with, see [`Value::display`].
+pub struct DisplayedValue<'a> {
+    value: &'a Value,
+    settings: &'a DisplaySettings,
 }
 
 const REAL_PRECISION: usize = 100;
 
-fn create_real(integer: i64) -> FBig {
+thread_local! {
+    static REALS_CREATED: Cell<usize> = const { Cell::new(0) };
+}
+
+/// How many SmallInts this thread has promoted to full-precision Decimals with
+/// [`create_real`], so that benchmarks can check integer arithmetic stays on integers.
+pub fn reals_created() -> usize {
+    REALS_CREATED.with(Cell::get)
+}
+
+pub fn create_real(integer: i64) -> FBig {
+    REALS_CREATED.with(|count| count.set(count.get() + 1));
     FBig::from(integer).with_precision(REAL_PRECISION).value()
 }
 
-fn safe_division(a: FBig, b: FBig) -> Result<FBig, RuntimeError> {
+/// Converts a SmallInt or Rational to a Decimal, leaving Decimals as they are.
+pub fn as_real(value: &Value) -> Option<FBig> {
+    match value {
+        Value::SmallInt(value) => Some(create_real(*value)),
+        Value::Real(value) => Some(value.clone()),
+        Value::Rational(value) => Some(rational_to_real(value)),
+        _ => None,
+    }
+}
+
+fn rational_to_real(value: &RBig) -> FBig {
+    FBig::from(value.numerator().clone())
+        .with_precision(REAL_PRECISION)
+        .value()
+        / FBig::from(value.denominator().clone())
+            .with_precision(REAL_PRECISION)
+            .value()
+}
+
+/// `a / b`, or `None` if `b` is zero.
+fn checked_rational_division(a: &RBig, b: &RBig) -> Option<RBig> {
+    (!b.is_zero()).then(|| a / b)
+}
+
+/// `base ^ exponent`, or `None` when raising zero to a negative power.
+fn rational_power(base: &RBig, exponent: i64) -> Option<RBig> {
+    let power = base.pow(exponent.unsigned_abs() as usize);
+    if exponent < 0 {
+        checked_rational_division(&RBig::ONE, &power)
+    } else {
+        Some(power)
+    }
+}
+
+/// Views any numeric value as the real and imaginary parts of a complex number.
+fn as_complex(value: &Value) -> Option<(FBig, FBig)> {
+    match value {
+        Value::Complex { re, im } => Some((re.clone(), im.clone())),
+        value => as_real(value).map(|re| (re, create_real(0))),
+    }
+}
+
+type ComplexParts = (FBig, FBig);
+
+fn complex_arithmetic(
+    a: &Value,
+    b: &Value,
+    operation: &str,
+    function: fn(ComplexParts, ComplexParts) -> Result<ComplexParts, RuntimeError>,
+) -> Result<Value, RuntimeError> {
+    match (as_complex(a), as_complex(b)) {
+        (Some(a), Some(b)) => function(a, b).map(|(re, im)| Value::Complex { re, im }),
+        _ => Err(RuntimeError::TypeMismatch {
+            first: a.type_name(),
+            last: b.type_name(),
+            operation: operation.to_string(),
+        }),
+    }
+}
+
+fn complex_multiplication((a, b): ComplexParts, (c, d): ComplexParts) -> ComplexParts {
+    (&a * &c - &b * &d, a * d + b * c)
+}
+
+fn complex_division(
+    (a, b): ComplexParts,
+    (c, d): ComplexParts,
+) -> Result<ComplexParts, RuntimeError> {
+    let denominator = &c * &c + &d * &d;
+    Ok((
+        safe_division(&a * &c + &b * &d, denominator.clone(), None)?,
+        safe_division(b * c - a * d, denominator, None)?,
+    ))
+}
+
+/// Raises a complex number to a SmallInt power by repeated squaring.
+fn complex_power(base: &Value, exponent: &Value) -> Result<Value, RuntimeError> {
+    let exponent = match exponent {
+        Value::SmallInt(exponent) => *exponent,
+        Value::Complex { .. } | Value::Real(_) | Value::Rational(_) => {
+            return Err(RuntimeError::InvalidArgument {
+                function: "^".to_string(),
+                reason: "complex powers need a SmallInt exponent".to_string(),
+            })
+        }
+        exponent => {
+            return Err(RuntimeError::TypeMismatch {
+                first: base.type_name(),
+                last: exponent.type_name(),
+                operation: "^".to_string(),
+            })
+        }
+    };
+    let Some(mut square) = as_complex(base) else {
+        return Err(RuntimeError::TypeMismatch {
+            first: base.type_name(),
+            last: "SmallInt".to_string(),
+            operation: "^".to_string(),
+        });
+    };
+    let mut result = (create_real(1), create_real(0));
+    let mut remaining = exponent.unsigned_abs();
+    while remaining > 0 {
+        if remaining % 2 == 1 {
+            result = complex_multiplication(result, square.clone());
+        }
+        square = complex_multiplication(square.clone(), square);
+        remaining /= 2;
+    }
+    if exponent < 0 {
+        result = complex_division((create_real(1), create_real(0)), result)?;
+    }
+    let (re, im) = result;
+    Ok(Value::Complex { re, im })
+}
+
+/// Collapses a whole-number Rational to a SmallInt, or to a Decimal if it is out of range.
+pub fn rational_value(rational: RBig) -> Value {
+    if !rational.is_int() {
+        return Value::Rational(rational);
+    }
+    i64::try_from(rational.numerator())
+        .map(Value::SmallInt)
+        .unwrap_or_else(|_| Value::Real(rational_to_real(&rational)))
+}
+
+/// A pair of numeric operands brought to a common type.
+enum Promoted {
+    Exact(RBig, RBig),
+    Real(FBig, FBig),
+}
+
+/// Rational arithmetic stays exact with SmallInts but decays to Decimal alongside a Decimal.
+fn promote(a: &Value, b: &Value, operation: &str) -> Result<Promoted, RuntimeError> {
+    let exact = |value: &Value| match value {
+        Value::SmallInt(value) => Some(RBig::from(*value)),
+        Value::Rational(value) => Some(value.clone()),
+        _ => None,
+    };
+    if let (Some(a), Some(b)) = (exact(a), exact(b)) {
+        return Ok(Promoted::Exact(a, b));
+    }
+    match (as_real(a), as_real(b)) {
+        (Some(a), Some(b)) => Ok(Promoted::Real(a, b)),
+        _ => Err(RuntimeError::TypeMismatch {
+            first: a.type_name(),
+            last: b.type_name(),
+            operation: operation.to_string(),
+        }),
+    }
+}
+
+fn rational_arithmetic(
+    a: &Value,
+    b: &Value,
+    operation: &str,
+    exact: fn(&RBig, &RBig) -> Option<RBig>,
+    real: fn(FBig, FBig, Option<Rounding>) -> Result<FBig, RuntimeError>,
+    rounding: Option<Rounding>,
+) -> Result<Value, RuntimeError> {
+    Ok(match promote(a, b, operation)? {
+        Promoted::Exact(a, b) => rational_value(exact(&a, &b).ok_or(RuntimeError::DivisionByZero)?),
+        Promoted::Real(a, b) => Value::Real(real(a, b, rounding)?),
+    })
+}
+
+fn safe_division(a: FBig, b: FBig, rounding: Option<Rounding>) -> Result<FBig, RuntimeError> {
     if b == FBig::<mode::Zero>::ZERO {
         Err(RuntimeError::DivisionByZero)
     } else {
-        Ok(a / b)
+        Ok(rounded!(rounding, |a, b| a / b))
     }
 }
 
-fn safe_power(base: &Value, exponent: &Value) -> Result<Value, RuntimeError> {
-    Ok(match (base, exponent) {
-        (Value::SmallInt(base), Value::SmallInt(exponent)) => {
-            if *base >= 0 && *exponent >= 0 {
-                (*exponent as u64)
-                    .try_into()
-                    .map_err(|_| ())
-                    .and_then(|exponent| base.checked_pow(exponent).ok_or(()))
-                    .map(Value::SmallInt)
-                    .unwrap_or_else(|_| Value::Real(create_real(*base).powi((*exponent).into())))
-            } else {
-                Value::Real(create_real(*base).powi((*exponent).into()))
+/// Divides and truncates toward zero, giving a SmallInt unless the quotient is out of range.
+fn integer_division(
+    a: &Value,
+    b: &Value,
+    rounding: Option<Rounding>,
+) -> Result<Value, RuntimeError> {
+    let quotient = match (a, b) {
+        (Value::SmallInt(_), Value::SmallInt(0)) => return Err(RuntimeError::DivisionByZero),
+        (Value::SmallInt(a), Value::SmallInt(b)) => {
+            if let Some(quotient) = a.checked_div(*b) {
+                return Ok(Value::SmallInt(quotient));
             }
+            create_real(*a) / create_real(*b)
+        }
+        (Value::Real(a), Value::Real(b)) => safe_division(a.clone(), b.clone(), rounding)?,
+        (Value::Real(a), Value::SmallInt(b)) => {
+            safe_division(a.clone(), create_real(*b), rounding)?
+        }
+        (Value::SmallInt(a), Value::Real(b)) => {
+            safe_division(create_real(*a), b.clone(), rounding)?
+        }
+        (Value::Rational(_), _) | (_, Value::Rational(_)) => match promote(a, b, "//")? {
+            Promoted::Exact(a, b) => {
+                let quotient =
+                    checked_rational_division(&a, &b).ok_or(RuntimeError::DivisionByZero)?;
+                return Ok(rational_value(RBig::from(quotient.trunc())));
+            }
+            Promoted::Real(a, b) => safe_division(a, b, rounding)?,
+        },
+        (a, b) => {
+            return Err(RuntimeError::TypeMismatch {
+                first: a.type_name(),
+                last: b.type_name(),
+                operation: "//".to_string(),
+            })
+        }
+    };
+    let truncated = quotient.trunc();
+    Ok(i64::try_from(truncated.to_int().value())
+        .map(Value::SmallInt)
+        .unwrap_or(Value::Real(truncated)))
+}
+
+/// The magnitude of a number; for a Complex number this is its modulus, as a Decimal.
+pub fn absolute_value(value: &Value) -> Result<Value, RuntimeError> {
+    Ok(match value {
+        Value::SmallInt(a) => a
+            .checked_abs()
+            .map(Value::SmallInt)
+            .unwrap_or_else(|| Value::Real(-create_real(*a))),
+        Value::Real(a) if a < &FBig::<mode::Zero>::ZERO => Value::Real(-a),
+        Value::Real(a) => Value::Real(a.clone()),
+        Value::Rational(a) if a.numerator() < &IBig::ZERO => Value::Rational(-a),
+        Value::Rational(a) => Value::Rational(a.clone()),
+        Value::Complex { re, im } => Value::Real((re * re + im * im).sqrt()),
+        value => {
+            return Err(RuntimeError::InvalidType {
+                found: value.type_name(),
+                operation: "abs".to_string(),
+            })
+        }
+    })
+}
+
+/// Applies an operation defined only on SmallInts, such as the bitwise operators.
+fn integer_operation(
+    a: &Value,
+    b: &Value,
+    operation: &str,
+    function: fn(i64, i64) -> Result<i64, RuntimeError>,
+) -> Result<Value, RuntimeError> {
+    match (a, b) {
+        (Value::SmallInt(a), Value::SmallInt(b)) => function(*a, *b).map(Value::SmallInt),
+        (Value::SmallInt(_), other) | (other, _) => Err(RuntimeError::InvalidType {
+            found: other.type_name(),
+            operation: operation.to_string(),
+        }),
+    }
+}
+
+fn check_shift(amount: i64, operation: &str) -> Result<(), RuntimeError> {
+    if amount < 0 {
+        Err(RuntimeError::InvalidArgument {
+            function: operation.to_string(),
+            reason: "negative shift amount".to_string(),
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// Shifts left, failing if any set bits (or the sign) would be lost.
+fn shift_left(value: i64, amount: i64) -> Result<i64, RuntimeError> {
+    check_shift(amount, "<<")?;
+    let shifted = if amount < 64 { value << amount } else { 0 };
+    if (amount >= 64 && value != 0) || shifted >> amount.min(63) != value {
+        return Err(RuntimeError::InvalidArgument {
+            function: "<<".to_string(),
+            reason: "result does not fit in a SmallInt".to_string(),
+        });
+    }
+    Ok(shifted)
+}
+
+/// Shifts right arithmetically; shifting by 64 or more leaves only the sign.
+fn shift_right(value: i64, amount: i64) -> Result<i64, RuntimeError> {
+    check_shift(amount, ">>")?;
+    Ok(value >> amount.min(63))
+}
+
+/// The result of a SmallInt operation: `checked` if it is in range, and otherwise whatever
+/// `overflow` calls for.
+fn small_int_result(
+    overflow: OverflowMode,
+    operation: &str,
+    checked: Option<i64>,
+    wrapped: impl FnOnce() -> i64,
+    promoted: impl FnOnce() -> Value,
+) -> Result<Value, RuntimeError> {
+    Ok(match (checked, overflow) {
+        (Some(result), _) => Value::SmallInt(result),
+        (None, OverflowMode::Promote) => promoted(),
+        (None, OverflowMode::Wrap) => Value::SmallInt(wrapped()),
+        (None, OverflowMode::Error) => {
+            return Err(RuntimeError::IntegerOverflow {
+                operation: operation.to_string(),
+            })
+        }
+    })
+}
+
+/// Raises to a power by repeated squaring, wrapping on overflow.
+fn wrapping_power(mut base: i64, mut exponent: u64) -> i64 {
+    let mut result: i64 = 1;
+    while exponent > 0 {
+        if exponent % 2 == 1 {
+            result = result.wrapping_mul(base);
+        }
+        base = base.wrapping_mul(base);
+        exponent /= 2;
+    }
+    result
+}
+
+fn real_power(base: FBig, exponent: i64, rounding: Option<Rounding>) -> Value {
+    Value::Real(rounded!(rounding, |base| base.powi(exponent.into())))
+}
+
+/// Rationals raised to larger powers than this are computed as Decimals instead.
+const MAX_EXACT_EXPONENT: u64 = 1024;
+
+/// `base ^ exponent`.
+pub fn safe_power(
+    base: &Value,
+    exponent: &Value,
+    rounding: Option<Rounding>,
+    overflow: OverflowMode,
+) -> Result<Value, RuntimeError> {
+    // Zero to a negative power divides by zero.
+    let zero_base = match base {
+        Value::SmallInt(base) => *base == 0,
+        Value::Real(base) => *base == FBig::<mode::Zero>::ZERO,
+        _ => false,
+    };
+    if zero_base && numeric_ordering(exponent, &Value::SmallInt(0), "^").is_ok_and(Ordering::is_lt)
+    {
+        return Err(RuntimeError::DivisionByZero);
+    }
+    Ok(match (base, exponent) {
+        // Only a negative exponent or an overflowing result needs a Decimal; a negative base
+        // on its own stays exact.
+        (Value::SmallInt(base), Value::SmallInt(exponent)) if *exponent < 0 => {
+            real_power(create_real(*base), *exponent, rounding)
+        }
+        (Value::SmallInt(base), Value::SmallInt(exponent)) => small_int_result(
+            overflow,
+            "^",
+            u32::try_from(*exponent)
+                .ok()
+                .and_then(|exponent| base.checked_pow(exponent)),
+            || wrapping_power(*base, *exponent as u64),
+            || real_power(create_real(*base), *exponent, rounding),
+        )?,
+        (Value::Real(base), Value::Real(exponent)) => {
+            let (base, exponent) = (base.clone(), exponent.clone());
+            Value::Real(rounded!(rounding, |base, exponent| base.powf(&exponent)))
         }
-        (Value::Real(base), Value::Real(exponent)) => Value::Real(base.powf(exponent)),
         (Value::Real(base), Value::SmallInt(exponent)) => {
-            Value::Real(base.powi((*exponent).into()))
+            real_power(base.clone(), *exponent, rounding)
         }
         (Value::SmallInt(base), Value::Real(exponent)) => {
-            Value::Real(create_real(*base).powf(exponent))
+            let (base, exponent) = (create_real(*base), exponent.clone());
+            Value::Real(rounded!(rounding, |base, exponent| base.powf(&exponent)))
         }
+        (Value::Complex { .. }, _) | (_, Value::Complex { .. }) => complex_power(base, exponent)?,
+        (Value::Rational(base), Value::SmallInt(exponent))
+            if exponent.unsigned_abs() <= MAX_EXACT_EXPONENT =>
+        {
+            rational_value(rational_power(base, *exponent).ok_or(RuntimeError::DivisionByZero)?)
+        }
+        (Value::Rational(_), _) | (_, Value::Rational(_)) => match promote(base, exponent, "^")? {
+            Promoted::Exact(base, exponent) => {
+                let (base, exponent) = (rational_to_real(&base), rational_to_real(&exponent));
+                Value::Real(rounded!(rounding, |base, exponent| base.powf(&exponent)))
+            }
+            Promoted::Real(base, exponent) => {
+                Value::Real(rounded!(rounding, |base, exponent| base.powf(&exponent)))
+            }
+        },
         (base, exponent) => {
             return Err(RuntimeError::TypeMismatch {
                 first: base.type_name(),
@@ -83,172 +732,1149 @@ fn safe_power(base: &Value, exponent: &Value) -> Result<Value, RuntimeError> {
     })
 }
 
+/// `a + b`, which also joins strings.
+pub fn add(
+    a: Value,
+    b: Value,
+    rounding: Option<Rounding>,
+    overflow: OverflowMode,
+) -> Result<Value, RuntimeError> {
+    Ok(match (a, b) {
+        (Value::SmallInt(a), Value::SmallInt(b)) => small_int_result(
+            overflow,
+            "+",
+            a.checked_add(b),
+            || a.wrapping_add(b),
+            || Value::Real(create_real(a) + create_real(b)),
+        )?,
+        (Value::Real(a), Value::Real(b)) => Value::Real(rounded!(rounding, |a, b| a + b)),
+        (Value::Real(a), Value::SmallInt(b)) => {
+            let b = create_real(b);
+            Value::Real(rounded!(rounding, |a, b| a + b))
+        }
+        (Value::SmallInt(a), Value::Real(b)) => {
+            let a = create_real(a);
+            Value::Real(rounded!(rounding, |a, b| a + b))
+        }
+        (Value::Str(a), Value::Str(b)) => Value::Str(a + &b),
+        (a @ Value::Complex { .. }, b) | (a, b @ Value::Complex { .. }) => {
+            complex_arithmetic(&a, &b, "+", |(a, b), (c, d)| Ok((a + c, b + d)))?
+        }
+        (a @ Value::Rational(_), b) | (a, b @ Value::Rational(_)) => rational_arithmetic(
+            &a,
+            &b,
+            "+",
+            |a, b| Some(a + b),
+            |a, b, rounding| Ok(rounded!(rounding, |a, b| a + b)),
+            rounding,
+        )?,
+        (a, b) => {
+            return Err(RuntimeError::TypeMismatch {
+                first: a.type_name(),
+                last: b.type_name(),
+                operation: "+".to_string(),
+            })
+        }
+    })
+}
+
+/// `a - b`.
+pub fn subtract(
+    a: Value,
+    b: Value,
+    rounding: Option<Rounding>,
+    overflow: OverflowMode,
+) -> Result<Value, RuntimeError> {
+    Ok(match (a, b) {
+        (Value::SmallInt(a), Value::SmallInt(b)) => small_int_result(
+            overflow,
+            "-",
+            a.checked_sub(b),
+            || a.wrapping_sub(b),
+            || Value::Real(create_real(a) - create_real(b)),
+        )?,
+        (Value::Real(a), Value::Real(b)) => Value::Real(rounded!(rounding, |a, b| a - b)),
+        (Value::Real(a), Value::SmallInt(b)) => {
+            let b = create_real(b);
+            Value::Real(rounded!(rounding, |a, b| a - b))
+        }
+        (Value::SmallInt(a), Value::Real(b)) => {
+            let a = create_real(a);
+            Value::Real(rounded!(rounding, |a, b| a - b))
+        }
+        (a @ Value::Complex { .. }, b) | (a, b @ Value::Complex { .. }) => {
+            complex_arithmetic(&a, &b, "-", |(a, b), (c, d)| Ok((a - c, b - d)))?
+        }
+        (a @ Value::Rational(_), b) | (a, b @ Value::Rational(_)) => rational_arithmetic(
+            &a,
+            &b,
+            "-",
+            |a, b| Some(a - b),
+            |a, b, rounding| Ok(rounded!(rounding, |a, b| a - b)),
+            rounding,
+        )?,
+        (a, b) => {
+            return Err(RuntimeError::TypeMismatch {
+                first: a.type_name(),
+                last: b.type_name(),
+                operation: "-".to_string(),
+            })
+        }
+    })
+}
+
+/// `a * b`.
+pub fn multiply(
+    a: Value,
+    b: Value,
+    rounding: Option<Rounding>,
+    overflow: OverflowMode,
+) -> Result<Value, RuntimeError> {
+    Ok(match (a, b) {
+        (Value::SmallInt(a), Value::SmallInt(b)) => small_int_result(
+            overflow,
+            "*",
+            a.checked_mul(b),
+            || a.wrapping_mul(b),
+            || Value::Real(create_real(a) * create_real(b)),
+        )?,
+        (Value::Real(a), Value::Real(b)) => Value::Real(rounded!(rounding, |a, b| a * b)),
+        (Value::Real(a), Value::SmallInt(b)) => {
+            let b = create_real(b);
+            Value::Real(rounded!(rounding, |a, b| a * b))
+        }
+        (Value::SmallInt(a), Value::Real(b)) => {
+            let a = create_real(a);
+            Value::Real(rounded!(rounding, |a, b| a * b))
+        }
+        (a @ Value::Complex { .. }, b) | (a, b @ Value::Complex { .. }) => {
+            complex_arithmetic(&a, &b, "*", |a, b| Ok(complex_multiplication(a, b)))?
+        }
+        (a @ Value::Rational(_), b) | (a, b @ Value::Rational(_)) => rational_arithmetic(
+            &a,
+            &b,
+            "*",
+            |a, b| Some(a * b),
+            |a, b, rounding| Ok(rounded!(rounding, |a, b| a * b)),
+            rounding,
+        )?,
+        (a, b) => {
+            return Err(RuntimeError::TypeMismatch {
+                first: a.type_name(),
+                last: b.type_name(),
+                operation: "*".to_string(),
+            })
+        }
+    })
+}
+
+/// `a / b`, which is exact for SmallInts and Rationals.
+pub fn divide(a: Value, b: Value, rounding: Option<Rounding>) -> Result<Value, RuntimeError> {
+    Ok(match (a, b) {
+        (Value::SmallInt(a), Value::SmallInt(b)) => {
+            if b != 0 && a.checked_rem(b) == Some(0) {
+                Value::SmallInt(a / b)
+            } else {
+                checked_rational_division(&RBig::from(a), &RBig::from(b))
+                    .map(rational_value)
+                    .ok_or(RuntimeError::DivisionByZero)?
+            }
+        }
+        (Value::Real(a), Value::Real(b)) => Value::Real(safe_division(a, b, rounding)?),
+        (Value::Real(a), Value::SmallInt(b)) => {
+            Value::Real(safe_division(a, create_real(b), rounding)?)
+        }
+        (Value::SmallInt(a), Value::Real(b)) => {
+            Value::Real(safe_division(create_real(a), b, rounding)?)
+        }
+        (a @ Value::Complex { .. }, b) | (a, b @ Value::Complex { .. }) => {
+            complex_arithmetic(&a, &b, "/", complex_division)?
+        }
+        (a @ Value::Rational(_), b) | (a, b @ Value::Rational(_)) => rational_arithmetic(
+            &a,
+            &b,
+            "/",
+            checked_rational_division,
+            safe_division,
+            rounding,
+        )?,
+        (a, b) => {
+            return Err(RuntimeError::TypeMismatch {
+                first: a.type_name(),
+                last: b.type_name(),
+                operation: "/".to_string(),
+            })
+        }
+    })
+}
+
+fn factorial(operand: &Value) -> Result<Value, RuntimeError> {
+    let n = match operand {
+        Value::SmallInt(n) if *n < 0 => {
+            return Err(RuntimeError::InvalidArgument {
+                function: "!".to_string(),
+                reason: "factorial of a negative number".to_string(),
+            })
+        }
+        Value::SmallInt(n) => *n,
+        operand => {
+            return Err(RuntimeError::InvalidType {
+                found: operand.type_name(),
+                operation: "!".to_string(),
+            })
+        }
+    };
+    let mut result: i64 = 1;
+    for i in 2..=n {
+        match result.checked_mul(i) {
+            Some(product) => result = product,
+            None => {
+                let real = (i..=n).fold(create_real(result), |product, i| product * create_real(i));
+                return Ok(Value::Real(real));
+            }
+        }
+    }
+    Ok(Value::SmallInt(result))
+}
+
+pub fn numeric_ordering(a: &Value, b: &Value, operation: &str) -> Result<Ordering, RuntimeError> {
+    Ok(match (a, b) {
+        (Value::SmallInt(a), Value::SmallInt(b)) => a.cmp(b),
+        (Value::Real(a), Value::Real(b)) => a.cmp(b),
+        (Value::Real(a), Value::SmallInt(b)) => a.cmp(&create_real(*b)),
+        (Value::SmallInt(a), Value::Real(b)) => create_real(*a).cmp(b),
+        (Value::Rational(_), _) | (_, Value::Rational(_)) => match promote(a, b, operation)? {
+            Promoted::Exact(a, b) => a.cmp(&b),
+            Promoted::Real(a, b) => a.cmp(&b),
+        },
+        (a, b) => {
+            return Err(RuntimeError::TypeMismatch {
+                first: a.type_name(),
+                last: b.type_name(),
+                operation: operation.to_string(),
+            })
+        }
+    })
+}
+
+/// Compares values the way `==` does, naming `operation` if they can't be compared.
+pub fn values_equal(a: &Value, b: &Value, operation: &str) -> Result<bool, RuntimeError> {
+    match (a, b) {
+        (Value::Bool(a), Value::Bool(b)) => Ok(a == b),
+        (Value::Str(a), Value::Str(b)) => Ok(a == b),
+        (Value::List(a), Value::List(b)) | (Value::Tuple(a), Value::Tuple(b)) => {
+            if a.len() != b.len() {
+                return Ok(false);
+            }
+            for (a, b) in a.iter().zip(b) {
+                if !values_equal(a, b, operation)? {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        }
+        (Value::Complex { .. }, _) | (_, Value::Complex { .. }) => {
+            match (as_complex(a), as_complex(b)) {
+                (Some(a), Some(b)) => Ok(a == b),
+                _ => Err(RuntimeError::TypeMismatch {
+                    first: a.type_name(),
+                    last: b.type_name(),
+                    operation: operation.to_string(),
+                }),
+            }
+        }
+        (a, b) => numeric_ordering(a, b, operation).map(Ordering::is_eq),
+    }
+}
+
+/// Values are ordered first by kind: booleans, then numbers, strings, lists, tuples, builtins
+/// and user functions. Numbers of any type are compared by value, so `1`, `1.0` and `1 + 0i` are
+/// all equal, and complex numbers are ordered by real part and then imaginary part. Reals
+/// can't be NaN, so this is a total order. Lists and tuples are compared element by element, builtins by
+/// name, and user functions by name, parameters, body and any arguments already supplied.
+impl Ord for Value {
+    fn cmp(&self, other: &Self) -> Ordering {
+        fn rank(value: &Value) -> u8 {
+            match value {
+                Value::Bool(_) => 0,
+                Value::SmallInt(_)
+                | Value::Rational(_)
+                | Value::Real(_)
+                | Value::Complex { .. } => 1,
+                Value::Str(_) => 2,
+                Value::List(_) => 3,
+                Value::Tuple(_) => 4,
+                Value::NativeFunction(_) => 5,
+                Value::Function(_) => 6,
+            }
+        }
+        match (self, other) {
+            (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+            (Value::Str(a), Value::Str(b)) => a.cmp(b),
+            (Value::List(a), Value::List(b)) | (Value::Tuple(a), Value::Tuple(b)) => a.cmp(b),
+            (Value::NativeFunction(a), Value::NativeFunction(b)) => a.name.cmp(b.name),
+            (Value::Function(a), Value::Function(b)) => (&a.name, &a.parameter_names)
+                .cmp(&(&b.name, &b.parameter_names))
+                .then_with(|| a.body.to_string().cmp(&b.body.to_string()))
+                .then_with(|| a.captured.cmp(&b.captured)),
+            (Value::Complex { .. }, _) | (_, Value::Complex { .. }) => {
+                match (as_complex(self), as_complex(other)) {
+                    (Some(a), Some(b)) => a.cmp(&b),
+                    _ => rank(self).cmp(&rank(other)),
+                }
+            }
+            (a, b) => numeric_ordering(a, b, "compare").unwrap_or_else(|_| rank(a).cmp(&rank(b))),
+        }
+    }
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Value {}
+
+/// The arguments of a call to a memoized function. Unlike `==`, this tells numbers of
+/// different types apart, even inside lists and tuples, so that `f(1)` and `f(1.0)` are cached
+/// separately.
+#[derive(Debug, Clone)]
+pub struct MemoKey(pub Vec<Value>);
+
+impl MemoKey {
+    fn compare(a: &Value, b: &Value) -> Ordering {
+        fn kind(value: &Value) -> u8 {
+            match value {
+                Value::SmallInt(_) => 0,
+                Value::Real(_) => 1,
+                Value::Rational(_) => 2,
+                Value::Complex { .. } => 3,
+                Value::Bool(_) => 4,
+                Value::Str(_) => 5,
+                Value::List(_) => 6,
+                Value::Tuple(_) => 7,
+                Value::Function(_) => 8,
+                Value::NativeFunction(_) => 9,
+            }
+        }
+        kind(a).cmp(&kind(b)).then_with(|| match (a, b) {
+            (Value::List(a), Value::List(b)) | (Value::Tuple(a), Value::Tuple(b)) => {
+                MemoKey::compare_all(a, b)
+            }
+            (a, b) => a.cmp(b),
+        })
+    }
+
+    fn compare_all(a: &[Value], b: &[Value]) -> Ordering {
+        a.iter()
+            .zip(b)
+            .map(|(a, b)| MemoKey::compare(a, b))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| a.len().cmp(&b.len()))
+    }
+}
+
+impl Ord for MemoKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        MemoKey::compare_all(&self.0, &other.0)
+    }
+}
+
+impl PartialOrd for MemoKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for MemoKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for MemoKey {}
+
+impl NativeFunction {
+    pub fn call(
+        &self,
+        context: &mut EvalContext,
+        variables: &BTreeMap<String, Value>,
+        arguments: &[Value],
+    ) -> Result<Value, RuntimeError> {
+        let too_many = self
+            .maximum_arguments
+            .is_some_and(|maximum| arguments.len() > maximum);
+        if arguments.len() < self.minimum_arguments || too_many {
+            return Err(RuntimeError::ParameterMismatch {
+                expected: if too_many {
+                    self.maximum_arguments.unwrap()
+                } else {
+                    self.minimum_arguments
+                },
+                found: arguments.len(),
+            });
+        }
+        (self.function)(context, variables, arguments)
+    }
+}
+
 impl Value {
+    pub fn display<'a>(&'a self, settings: &'a DisplaySettings) -> DisplayedValue<'a> {
+        DisplayedValue {
+            value: self,
+            settings,
+        }
+    }
+
     pub fn type_name(&self) -> String {
         match self {
             Value::SmallInt(_) => "SmallInt".to_string(),
             Value::Real(_) => "Decimal".to_string(),
-            Value::Function(_) => "Function".to_string(),
+            Value::Rational(_) => "Rational".to_string(),
+            Value::Complex { .. } => "Complex".to_string(),
+            Value::Bool(_) => "Bool".to_string(),
+            Value::Str(_) => "Str".to_string(),
+            Value::List(_) => "List".to_string(),
+            Value::Tuple(_) => "Tuple".to_string(),
+            Value::Function(_) | Value::NativeFunction(_) => "Function".to_string(),
+        }
+    }
+
+    /// Whether this value belongs to `typ`. The number types nest, so an integer is also a
+    /// `Real`; names this interpreter doesn't know accept anything.
+    pub fn has_type(&self, typ: &Type) -> bool {
+        match typ {
+            Type::Named(name) => match (name.as_str(), self) {
+                ("Natural", Value::SmallInt(value)) => *value >= 0,
+                ("Natural", _) => false,
+                ("Int" | "Integer", value) => matches!(value, Value::SmallInt(_)),
+                ("Rational", value) => matches!(value, Value::SmallInt(_) | Value::Rational(_)),
+                ("Real", value) => matches!(
+                    value,
+                    Value::SmallInt(_) | Value::Rational(_) | Value::Real(_)
+                ),
+                ("Complex", value) => matches!(
+                    value,
+                    Value::SmallInt(_)
+                        | Value::Rational(_)
+                        | Value::Real(_)
+                        | Value::Complex { .. }
+                ),
+                ("Bool", value) => matches!(value, Value::Bool(_)),
+                ("Str", value) => matches!(value, Value::Str(_)),
+                ("List", value) => matches!(value, Value::List(_)),
+                ("Tuple", value) => matches!(value, Value::Tuple(_)),
+                ("Function", value) => {
+                    matches!(value, Value::Function(_) | Value::NativeFunction(_))
+                }
+                _ => true,
+            },
+            Type::Product(types) => match self {
+                Value::Tuple(elements) => {
+                    elements.len() == types.len()
+                        && elements
+                            .iter()
+                            .zip(types)
+                            .all(|(value, typ)| value.has_type(typ))
+                }
+                _ => false,
+            },
+        }
+    }
+
+    /// This value converted to `typ` where the number types allow it: integers and rationals
+    /// become reals for `Real`, and whole reals become integers for `Int` or `Natural`.
+    /// Returns `None` when no conversion applies.
+    pub fn converted_to(&self, typ: &Type) -> Option<Value> {
+        let Type::Named(name) = typ else {
+            return None;
+        };
+        match (name.as_str(), self) {
+            ("Real", Value::SmallInt(_) | Value::Rational(_)) => as_real(self).map(Value::Real),
+            ("Int" | "Integer" | "Natural", Value::Real(real)) if real.fract().repr().is_zero() => {
+                i64::try_from(real.to_int().value())
+                    .ok()
+                    .map(Value::SmallInt)
+            }
+            _ => None,
         }
     }
 
+    /// Evaluates `expression`. While tracing, each subexpression is printed to stderr with its
+    /// value once evaluated, indented by depth, so operands appear before what uses them.
     pub fn evaluate(
+        context: &mut EvalContext,
+        variables: &BTreeMap<String, Value>,
+        expression: &Expression,
+    ) -> Result<Value, RuntimeError> {
+        stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, || {
+            Value::evaluate_traced(context, variables, expression)
+        })
+    }
+
+    fn evaluate_traced(
+        context: &mut EvalContext,
+        variables: &BTreeMap<String, Value>,
+        expression: &Expression,
+    ) -> Result<Value, RuntimeError> {
+        let Some(depth) = context.trace_depth else {
+            return Value::evaluate_untraced(context, variables, expression);
+        };
+        context.trace_depth = Some(depth + 1);
+        let result = Value::evaluate_untraced(context, variables, expression);
+        context.trace_depth = Some(depth);
+        let indent = "  ".repeat(depth);
+        match &result {
+            Ok(value) => eprintln!(
+                "{}{} = {}",
+                indent,
+                expression,
+                value.display(&context.display)
+            ),
+            Err(error) => eprintln!("{}{} failed: {}", indent, expression, error),
+        }
+        result
+    }
+
+    fn evaluate_untraced(
+        context: &mut EvalContext,
         variables: &BTreeMap<String, Value>,
         expression: &Expression,
     ) -> Result<Value, RuntimeError> {
         Ok(match expression {
             Expression::Integer(value) => Value::SmallInt(*value),
-            Expression::Variable(name) => variables
+            Expression::Real(value) => {
+                let value = value.clone();
+                Value::Real(rounded!(context.rounding, |value| value
+                    .with_base_and_precision::<2>(REAL_PRECISION)
+                    .value()))
+            }
+            Expression::Bool(value) => Value::Bool(*value),
+            Expression::Str(value) => Value::Str(value.clone()),
+            Expression::Variable { name, span } => variables
                 .get(name)
-                .ok_or(RuntimeError::UnboundVariable(name.clone()))?
+                .ok_or_else(|| RuntimeError::UnboundVariable {
+                    name: name.clone(),
+                    span: *span,
+                })?
                 .clone(),
-                Expression::Negate(a) => {
-                match Value::evaluate(variables, a)? {
-                    Value::SmallInt(a) => Value::SmallInt(-a),
-                    Value::Real(a) => Value::Real(-a),
-                    a => {
-                        return Err(RuntimeError::InvalidType {
-                            found: a.type_name(),
-                            operation: "negate".to_string(),
-                        })
-                    }
+            Expression::Negate(a) => match Value::evaluate(context, variables, a)? {
+                Value::SmallInt(a) => a
+                    .checked_neg()
+                    .map(Value::SmallInt)
+                    .unwrap_or_else(|| Value::Real(-create_real(a))),
+                Value::Real(a) => Value::Real(-a),
+                Value::Rational(a) => Value::Rational(-&a),
+                Value::Complex { re, im } => Value::Complex { re: -re, im: -im },
+                a => {
+                    return Err(RuntimeError::InvalidType {
+                        found: a.type_name(),
+                        operation: "negate".to_string(),
+                    })
                 }
-            }
-            Expression::Add(a, b) => {
-                match (
-                    Value::evaluate(variables, a)?,
-                    Value::evaluate(variables, b)?,
-                ) {
-                    (Value::SmallInt(a), Value::SmallInt(b)) => a
-                        .checked_add(b)
-                        .map(Value::SmallInt)
-                        .unwrap_or_else(|| Value::Real(create_real(a) + create_real(b))),
-                    (Value::Real(a), Value::Real(b)) => Value::Real(a + b),
-                    (Value::Real(a), Value::SmallInt(b)) => Value::Real(a + create_real(b)),
-                    (Value::SmallInt(a), Value::Real(b)) => Value::Real(create_real(a) + b),
-                    (a, b) => {
-                        return Err(RuntimeError::TypeMismatch {
-                            first: a.type_name(),
-                            last: b.type_name(),
-                            operation: "+".to_string(),
-                        })
+            },
+            Expression::Add(a, b) => add(
+                Value::evaluate(context, variables, a)?,
+                Value::evaluate(context, variables, b)?,
+                context.rounding,
+                context.overflow,
+            )?,
+            Expression::Subtract(a, b) => subtract(
+                Value::evaluate(context, variables, a)?,
+                Value::evaluate(context, variables, b)?,
+                context.rounding,
+                context.overflow,
+            )?,
+            Expression::Multiply(a, b) => multiply(
+                Value::evaluate(context, variables, a)?,
+                Value::evaluate(context, variables, b)?,
+                context.rounding,
+                context.overflow,
+            )?,
+            Expression::Divide(a, b) => divide(
+                Value::evaluate(context, variables, a)?,
+                Value::evaluate(context, variables, b)?,
+                context.rounding,
+            )?,
+            Expression::IntegerDivide(a, b) => integer_division(
+                &Value::evaluate(context, variables, a)?,
+                &Value::evaluate(context, variables, b)?,
+                context.rounding,
+            )?,
+            Expression::Power(a, b) => safe_power(
+                &Value::evaluate(context, variables, a)?,
+                &Value::evaluate(context, variables, b)?,
+                context.rounding,
+                context.overflow,
+            )?,
+            Expression::Abs(a) => absolute_value(&Value::evaluate(context, variables, a)?)?,
+            Expression::Factorial(a) => factorial(&Value::evaluate(context, variables, a)?)?,
+            Expression::Equal(a, b) => Value::Bool(values_equal(
+                &Value::evaluate(context, variables, a)?,
+                &Value::evaluate(context, variables, b)?,
+                "==",
+            )?),
+            Expression::NotEqual(a, b) => Value::Bool(!values_equal(
+                &Value::evaluate(context, variables, a)?,
+                &Value::evaluate(context, variables, b)?,
+                "!=",
+            )?),
+            Expression::LessThan(a, b) => Value::Bool(
+                numeric_ordering(
+                    &Value::evaluate(context, variables, a)?,
+                    &Value::evaluate(context, variables, b)?,
+                    "<",
+                )?
+                .is_lt(),
+            ),
+            Expression::LessThanOrEqual(a, b) => Value::Bool(
+                numeric_ordering(
+                    &Value::evaluate(context, variables, a)?,
+                    &Value::evaluate(context, variables, b)?,
+                    "<=",
+                )?
+                .is_le(),
+            ),
+            Expression::GreaterThan(a, b) => Value::Bool(
+                numeric_ordering(
+                    &Value::evaluate(context, variables, a)?,
+                    &Value::evaluate(context, variables, b)?,
+                    ">",
+                )?
+                .is_gt(),
+            ),
+            Expression::GreaterThanOrEqual(a, b) => Value::Bool(
+                numeric_ordering(
+                    &Value::evaluate(context, variables, a)?,
+                    &Value::evaluate(context, variables, b)?,
+                    ">=",
+                )?
+                .is_ge(),
+            ),
+            Expression::BitAnd(a, b) => integer_operation(
+                &Value::evaluate(context, variables, a)?,
+                &Value::evaluate(context, variables, b)?,
+                "&",
+                |a, b| Ok(a & b),
+            )?,
+            Expression::BitOr(a, b) => integer_operation(
+                &Value::evaluate(context, variables, a)?,
+                &Value::evaluate(context, variables, b)?,
+                "|",
+                |a, b| Ok(a | b),
+            )?,
+            Expression::BitXor(a, b) => integer_operation(
+                &Value::evaluate(context, variables, a)?,
+                &Value::evaluate(context, variables, b)?,
+                "xor",
+                |a, b| Ok(a ^ b),
+            )?,
+            Expression::ShiftLeft(a, b) => integer_operation(
+                &Value::evaluate(context, variables, a)?,
+                &Value::evaluate(context, variables, b)?,
+                "<<",
+                shift_left,
+            )?,
+            Expression::ShiftRight(a, b) => integer_operation(
+                &Value::evaluate(context, variables, a)?,
+                &Value::evaluate(context, variables, b)?,
+                ">>",
+                shift_right,
+            )?,
+            Expression::And(a, b) => {
+                let mut result = true;
+                for operand in [a, b] {
+                    match Value::evaluate(context, variables, operand)? {
+                        Value::Bool(true) => {}
+                        Value::Bool(false) => {
+                            result = false;
+                            break;
+                        }
+                        operand => {
+                            return Err(RuntimeError::InvalidType {
+                                found: operand.type_name(),
+                                operation: "and".to_string(),
+                            })
+                        }
                     }
                 }
+                Value::Bool(result)
             }
-            Expression::Subtract(a, b) => {
-                match (
-                    Value::evaluate(variables, a)?,
-                    Value::evaluate(variables, b)?,
-                ) {
-                    (Value::SmallInt(a), Value::SmallInt(b)) => a
-                        .checked_sub(b)
-                        .map(Value::SmallInt)
-                        .unwrap_or_else(|| Value::Real(create_real(a) - create_real(b))),
-                    (Value::Real(a), Value::Real(b)) => Value::Real(a - b),
-                    (Value::Real(a), Value::SmallInt(b)) => Value::Real(a - create_real(b)),
-                    (Value::SmallInt(a), Value::Real(b)) => Value::Real(create_real(a) - b),
-                    (a, b) => {
-                        return Err(RuntimeError::TypeMismatch {
-                            first: a.type_name(),
-                            last: b.type_name(),
-                            operation: "-".to_string(),
-                        })
+            Expression::If {
+                condition,
+                then,
+                otherwise,
+            } => match Value::evaluate(context, variables, condition)? {
+                Value::Bool(true) => Value::evaluate(context, variables, then)?,
+                Value::Bool(false) => Value::evaluate(context, variables, otherwise)?,
+                condition => {
+                    return Err(RuntimeError::InvalidType {
+                        found: condition.type_name(),
+                        operation: "?".to_string(),
+                    })
+                }
+            },
+            Expression::Guarded {
+                branches,
+                otherwise,
+            } => {
+                let mut chosen = otherwise.as_deref();
+                for (condition, result) in branches {
+                    match Value::evaluate(context, variables, condition)? {
+                        Value::Bool(true) => {
+                            chosen = Some(result);
+                            break;
+                        }
+                        Value::Bool(false) => {}
+                        condition => {
+                            return Err(RuntimeError::InvalidType {
+                                found: condition.type_name(),
+                                operation: "if".to_string(),
+                            })
+                        }
                     }
                 }
+                match chosen {
+                    Some(result) => Value::evaluate(context, variables, result)?,
+                    None => return Err(RuntimeError::NoMatchingGuard),
+                }
             }
-            Expression::Multiply(a, b) => {
-                match (
-                    Value::evaluate(variables, a)?,
-                    Value::evaluate(variables, b)?,
-                ) {
-                    (Value::SmallInt(a), Value::SmallInt(b)) => a
-                        .checked_mul(b)
-                        .map(Value::SmallInt)
-                        .unwrap_or_else(|| Value::Real(create_real(a) * create_real(b))),
-                    (Value::Real(a), Value::Real(b)) => Value::Real(a * b),
-                    (Value::Real(a), Value::SmallInt(b)) => Value::Real(a * create_real(b)),
-                    (Value::SmallInt(a), Value::Real(b)) => Value::Real(create_real(a) * b),
-                    (a, b) => {
-                        return Err(RuntimeError::TypeMismatch {
-                            first: a.type_name(),
-                            last: b.type_name(),
-                            operation: "*".to_string(),
+            Expression::Match { scrutinee, arms } => {
+                let scrutinee = Value::evaluate(context, variables, scrutinee)?;
+                let arm = arms.iter().find(|(pattern, _)| match pattern {
+                    Pattern::Integer(integer) => scrutinee == Value::SmallInt(*integer),
+                    Pattern::Wildcard => true,
+                });
+                match arm {
+                    Some((_, result)) => Value::evaluate(context, variables, result)?,
+                    None => {
+                        return Err(RuntimeError::NonExhaustiveMatch {
+                            value: scrutinee.display(&context.display).to_string(),
                         })
                     }
                 }
             }
-            Expression::Divide(a, b) => {
-                match (
-                    Value::evaluate(variables, a)?,
-                    Value::evaluate(variables, b)?,
-                ) {
-                    (Value::SmallInt(a), Value::SmallInt(b)) => {
-                        if b != 0 && a % b == 0 {
-                            Value::SmallInt(a / b)
-                        } else {
-                            Value::Real(safe_division(create_real(a), create_real(b))?)
-                        }
-                    }
-                    (Value::Real(a), Value::Real(b)) => Value::Real(a / b),
-                    (Value::Real(a), Value::SmallInt(b)) => {
-                        Value::Real(safe_division(a, create_real(b))?)
-                    }
-                    (Value::SmallInt(a), Value::Real(b)) => {
-                        Value::Real(safe_division(create_real(a), b)?)
+            Expression::List(elements) => Value::List(
+                elements
+                    .iter()
+                    .map(|element| Value::evaluate(context, variables, element))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Expression::Tuple(elements) => Value::Tuple(
+                elements
+                    .iter()
+                    .map(|element| Value::evaluate(context, variables, element))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Expression::Index { list, index } => {
+                let list = match Value::evaluate(context, variables, list)? {
+                    Value::List(list) | Value::Tuple(list) => list,
+                    list => {
+                        return Err(RuntimeError::InvalidType {
+                            found: list.type_name(),
+                            operation: "index".to_string(),
+                        })
                     }
-                    (a, b) => {
-                        return Err(RuntimeError::TypeMismatch {
-                            first: a.type_name(),
-                            last: b.type_name(),
-                            operation: "/".to_string(),
+                };
+                let index = match Value::evaluate(context, variables, index)? {
+                    Value::SmallInt(index) => index,
+                    index => {
+                        return Err(RuntimeError::InvalidType {
+                            found: index.type_name(),
+                            operation: "index".to_string(),
                         })
                     }
+                };
+                usize::try_from(index)
+                    .ok()
+                    .and_then(|position| list.get(position))
+                    .ok_or(RuntimeError::IndexOutOfRange {
+                        index,
+                        length: list.len(),
+                    })?
+                    .clone()
+            }
+            Expression::Block { bindings, result } => {
+                let mut scope = variables.clone();
+                for (name, value) in bindings {
+                    let value = Value::evaluate(context, &scope, value)?;
+                    scope.insert(name.clone(), value);
                 }
+                Value::evaluate(context, &scope, result)?
             }
-            Expression::Power(a, b) => safe_power(
-                &Value::evaluate(variables, a)?,
-                &Value::evaluate(variables, b)?,
-            )?,
+            Expression::Lambda {
+                parameters,
+                guards,
+                body,
+            } => Value::Function(Function {
+                name: None,
+                parameter_names: parameters.clone(),
+                guards: guards.clone(),
+                parameter_types: Vec::new(),
+                return_type: None,
+                body: (**body).clone(),
+                bindings: Vec::new(),
+                // Keep what the lambda uses from where it is written, so it still works once
+                // returned from the function that made it.
+                captured: expression
+                    .free_variables()
+                    .into_iter()
+                    .filter_map(|name| {
+                        let value = variables.get(&name)?.clone();
+                        Some((name, value))
+                    })
+                    .collect(),
+                supplied: 0,
+                memo_table: None,
+            }),
             Expression::ApplyFunction {
                 function,
                 arguments,
             } => {
-                let function = match Value::evaluate(variables, function)? {
-                    Value::Function(f) => f,
-                    _ => {
-                        return Err(RuntimeError::InvalidType {
-                            found: "function".to_string(),
-                            operation: "apply".to_string(),
-                        })
-                    }
-                };
-                if function.parameter_names.len() != arguments.len() {
-                    return Err(RuntimeError::ParameterMismatch {
-                        expected: function.parameter_names.len(),
-                        found: arguments.len(),
-                    });
+                let function = Value::evaluate(context, variables, function)?;
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| Value::evaluate(context, variables, argument))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Value::apply(context, variables, &function, arguments)?
+            }
+        })
+    }
+    /// Calls a function value with already-evaluated arguments. A user function given fewer
+    /// arguments than it has parameters returns a function expecting the rest.
+    pub fn apply(
+        context: &mut EvalContext,
+        variables: &BTreeMap<String, Value>,
+        function: &Value,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let function = match function {
+            Value::Function(f) => f,
+            Value::NativeFunction(native) => return native.call(context, variables, &arguments),
+            other => {
+                return Err(RuntimeError::InvalidType {
+                    found: other.type_name(),
+                    operation: "apply".to_string(),
+                })
+            }
+        };
+        if function.parameter_names.len() < arguments.len() {
+            return Err(RuntimeError::ParameterMismatch {
+                expected: function.parameter_names.len(),
+                found: arguments.len(),
+            });
+        }
+        for (position, ((parameter, typ), argument)) in function
+            .parameter_names
+            .iter()
+            .zip(&function.parameter_types)
+            .zip(&arguments)
+            .enumerate()
+        {
+            if !argument.has_type(typ) {
+                return Err(RuntimeError::ArgumentType {
+                    position: function.supplied + position + 1,
+                    parameter: parameter.clone(),
+                    expected: typ.to_string(),
+                    found: argument.type_name(),
+                });
+            }
+        }
+        let supplied = arguments.len();
+        let mut bound = function.captured.clone();
+        bound.extend(
+            function
+                .parameter_names
+                .iter()
+                .cloned()
+                .zip(arguments)
+                .filter(|(name, _)| name != IGNORED_PARAMETER),
+        );
+        if supplied < function.parameter_names.len() {
+            return Ok(Value::Function(Function {
+                parameter_names: function.parameter_names[supplied..].to_vec(),
+                parameter_types: function
+                    .parameter_types
+                    .get(supplied..)
+                    .unwrap_or_default()
+                    .to_vec(),
+                captured: bound,
+                supplied: function.supplied + supplied,
+                ..function.clone()
+            }));
+        }
+        // Bound arguments are keyed by name, so every call lists them in the same order.
+        let memo_key = function
+            .memo_table
+            .map(|table| (table, MemoKey(bound.values().cloned().collect())));
+        if let Some((table, key)) = &memo_key {
+            if let Some(result) = context.memo_tables[*table].get(key) {
+                return Ok(result.clone());
+            }
+        }
+        let mut new_variables = variables.clone();
+        new_variables.extend(bound);
+        if let (Some(call_counts), Some(name)) = (&mut context.call_counts, &function.name) {
+            *call_counts.entry(name.clone()).or_insert(0) += 1;
+        }
+        if let Some(limit) = context.recursion_limit {
+            if context.call_depth >= limit {
+                return Err(RuntimeError::RecursionLimitExceeded { limit });
+            }
+        }
+        context.call_depth += 1;
+        let result = Value::evaluate_body(context, &new_variables, function);
+        context.call_depth -= 1;
+        let mut result = result?;
+        if let Some(typ) = &function.return_type {
+            if let Some(converted) = result.converted_to(typ) {
+                result = converted;
+            }
+            if !result.has_type(typ) {
+                return Err(RuntimeError::ReturnType {
+                    function: function.name.clone().unwrap_or_default(),
+                    expected: typ.to_string(),
+                    found: result.type_name(),
+                });
+            }
+        }
+        if let Some((table, key)) = memo_key {
+            context.memo_tables[table].insert(key, result.clone());
+        }
+        Ok(result)
+    }
+
+    /// Checks a function's guards, binds its `where` clause and evaluates its body, once its
+    /// arguments are bound.
+    fn evaluate_body(
+        context: &mut EvalContext,
+        variables: &BTreeMap<String, Value>,
+        function: &Function,
+    ) -> Result<Value, RuntimeError> {
+        for (parameter, guard) in &function.guards {
+            match Value::evaluate(context, variables, guard)? {
+                Value::Bool(true) => {}
+                Value::Bool(false) => {
+                    return Err(RuntimeError::GuardViolation {
+                        parameter: parameter.clone(),
+                    })
                 }
-                let mut new_variables = variables.clone();
-                for (parameter_name, argument) in
-                    function.parameter_names.iter().zip(arguments.iter())
-                {
-                    new_variables.insert(
-                        parameter_name.clone(),
-                        Value::evaluate(variables, argument)?,
-                    );
+                guard => {
+                    return Err(RuntimeError::InvalidType {
+                        found: guard.type_name(),
+                        operation: "guard".to_string(),
+                    })
                 }
-                Value::evaluate(&new_variables, &function.body)?
             }
-        })
+        }
+        if function.bindings.is_empty() {
+            return Value::evaluate(context, variables, &function.body);
+        }
+        let mut scope = variables.clone();
+        for (name, value) in &function.bindings {
+            let value = Value::evaluate(context, &scope, value)?;
+            scope.insert(name.clone(), value);
+        }
+        Value::evaluate(context, &scope, &function.body)
+    }
+}
+
+/// Finds the innermost subexpression that fails to evaluate even though all of its operands
+/// evaluate successfully, returning it with its error. Returns `None` if evaluation succeeds.
+pub fn locate_failure<'a>(
+    context: &mut EvalContext,
+    variables: &BTreeMap<String, Value>,
+    expression: &'a Expression,
+) -> Option<(&'a Expression, RuntimeError)> {
+    let error = Value::evaluate(context, variables, expression).err()?;
+    for operand in expression.operands() {
+        if let Some(failure) = locate_failure(context, variables, operand) {
+            return Some(failure);
+        }
+    }
+    Some((expression, error))
+}
+
+/// The canonical text of a decimal: positional notation with no trailing zeros after the
+/// point, no point at all for whole numbers, and no sign on zero. So `2.50` shows as `2.5`,
+/// `100.0` as `100` and `-0.0` as `0`.
+fn format_decimal(decimal: &DBig) -> String {
+    let text = decimal.to_string();
+    let text = if text.contains('.') && !text.contains('e') {
+        text.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        &text
+    };
+    match text {
+        "-0" | "" => "0".to_string(),
+        text => text.to_string(),
     }
 }
 
+fn format_real(value: &FBig) -> String {
+    format_decimal(&value.to_decimal().value())
+}
+
 impl Display for Value {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         match self {
             Value::SmallInt(value) => write!(f, "{}", value),
-            Value::Real(value) => write!(f, "{}", value.to_decimal().value()),
-            Value::Function(_) => write!(f, "<function>"),
+            Value::Real(value) => write!(f, "{}", format_real(value)),
+            Value::Rational(value) => write!(f, "{}", value),
+            Value::Complex { re, im } if im < &FBig::<mode::Zero>::ZERO => {
+                write!(f, "{} - {}i", format_real(re), format_real(&-im))
+            }
+            Value::Complex { re, im } => write!(f, "{} + {}i", format_real(re), format_real(im)),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Str(value) => write!(f, "{}", value),
+            Value::List(elements) => {
+                write!(f, "[")?;
+                for (index, element) in elements.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                write!(f, "]")
+            }
+            Value::Tuple(elements) => {
+                write!(f, "(")?;
+                for (index, element) in elements.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                // A comma tells a tuple of one apart from a bracketed value.
+                if elements.len() == 1 {
+                    write!(f, ",")?;
+                }
+                write!(f, ")")
+            }
+            Value::Function(function) => {
+                let parameters = function.parameter_names.join(", ");
+                match &function.name {
+                    Some(name) => write!(f, "<function {}({})>", name, parameters),
+                    None => write!(f, "<lambda({})>", parameters),
+                }
+            }
+            Value::NativeFunction(native) if native.name.is_empty() => write!(f, "<function>"),
+            Value::NativeFunction(native) => write!(f, "<function {}>", native.name),
+        }
+    }
+}
+
+/// Writes a nonzero decimal outside `range` in scientific notation, or returns `None`.
+fn scientific_notation(decimal: &DBig, range: &ScientificRange) -> Option<String> {
+    let significand = decimal.repr().significand();
+    if *significand == IBig::ZERO {
+        return None;
+    }
+    let mut digits = significand.unsigned_abs().to_string();
+    let exponent = decimal.repr().exponent() + digits.len() as isize - 1;
+    if range.min_exponent < exponent && exponent < range.max_exponent {
+        return None;
+    }
+    let trimmed = digits.trim_end_matches('0').len();
+    digits.truncate(trimmed);
+    let sign = if *significand < IBig::ZERO { "-" } else { "" };
+    Some(if digits.len() > 1 {
+        format!("{}{}.{}e{}", sign, &digits[..1], &digits[1..], exponent)
+    } else {
+        format!("{}{}e{}", sign, digits, exponent)
+    })
+}
+
+/// Denominators up to this are tried when showing decimals as fractions.
+const MAX_FRACTION_DENOMINATOR: i64 = 1000;
+
+/// Writes a decimal that isn't whole as the fraction with the smallest denominator that is
+/// within a few units in its last place, or returns `None` if no small denominator is.
+fn fraction_notation(value: &FBig) -> Option<String> {
+    if value.fract().repr().is_zero() {
+        return None;
+    }
+    let tolerance = value.ulp() * create_real(4);
+    (2..=MAX_FRACTION_DENOMINATOR).find_map(|denominator| {
+        let denominator = create_real(denominator);
+        let scaled = value * &denominator;
+        let numerator = scaled.round();
+        let error = (scaled - &numerator).abs();
+        (error <= &tolerance * &denominator).then(|| {
+            format!(
+                "{}/{}",
+                numerator.to_int().value(),
+                denominator.to_int().value()
+            )
+        })
+    })
+}
+
+impl Display for DisplayedValue<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.value {
+            Value::Real(value) => {
+                if self.settings.fractions {
+                    if let Some(text) = fraction_notation(value) {
+                        return write!(f, "{}", text);
+                    }
+                }
+                let mut decimal = value.to_decimal().value();
+                if let Some(digits) = self.settings.significant_digits {
+                    decimal = decimal.with_precision(digits).value();
+                }
+                if let Some(range) = &self.settings.scientific {
+                    if let Some(text) = scientific_notation(&decimal, range) {
+                        return write!(f, "{}", text);
+                    }
+                }
+                let decimal = format_decimal(&decimal);
+                match self.settings.max_real_length {
+                    Some(limit) if decimal.len() > limit => {
+                        let digits = decimal.chars().filter(char::is_ascii_digit).count();
+                        write!(f, "{}... ({} digits)", &decimal[..limit], digits)
+                    }
+                    _ => write!(f, "{}", decimal),
+                }
+            }
+            Value::List(elements) => {
+                let shown = match self.settings.max_list_length {
+                    Some(limit) if elements.len() > limit => limit,
+                    _ => elements.len(),
+                };
+                write!(f, "[")?;
+                for (index, element) in elements[..shown].iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", element.display(self.settings))?;
+                }
+                if shown < elements.len() {
+                    write!(f, ", ..., {} elements", elements.len())?;
+                }
+                write!(f, "]")
+            }
+            Value::Tuple(elements) => {
+                write!(f, "(")?;
+                for (index, element) in elements.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", element.display(self.settings))?;
+                }
+                if elements.len() == 1 {
+                    write!(f, ",")?;
+                }
+                write!(f, ")")
+            }
+            value => write!(f, "{}", value),
         }
     }
 }
@@ -256,7 +1882,13 @@ impl Display for Value {
 impl Display for RuntimeError {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         match self {
-            RuntimeError::UnboundVariable(name) => write!(f, "Unbound variable: {}", name),
+            RuntimeError::UnboundVariable {
+                name,
+                span: Some(span),
+            } => write!(f, "Unbound variable: {} at {}", name, span),
+            RuntimeError::UnboundVariable { name, span: None } => {
+                write!(f, "Unbound variable: {}", name)
+            }
             RuntimeError::InvalidType { found, operation } => {
                 write!(f, "Invalid type: {} for operation {}", found, operation)
             }
@@ -266,8 +1898,8 @@ impl Display for RuntimeError {
                 operation,
             } => write!(
                 f,
-                "Type mismatch: {} {} {}",
-                first, operation, last
+                "Type mismatch: cannot apply '{}' to {} and {}",
+                operation, first, last
             ),
             RuntimeError::DivisionByZero => write!(f, "Division by zero"),
             RuntimeError::ParameterMismatch { expected, found } => write!(
@@ -275,6 +1907,85 @@ impl Display for RuntimeError {
                 "Parameter mismatch: expected {} arguments, found {}",
                 expected, found
             ),
+            RuntimeError::InvalidArgument { function, reason } => {
+                write!(f, "Invalid argument to {}: {}", function, reason)
+            }
+            RuntimeError::AssertionFailed { message } => write!(f, "Assertion failed: {}", message),
+            RuntimeError::RecursionLimitExceeded { limit } => write!(
+                f,
+                "Recursion limit exceeded: more than {} nested calls",
+                limit
+            ),
+            RuntimeError::IndexOutOfRange { index, length } => write!(
+                f,
+                "Index out of range: {} for a list of length {}",
+                index, length
+            ),
+            RuntimeError::ArgumentType {
+                position,
+                parameter,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Argument type mismatch: argument {} ({}) should be {}, found {}",
+                position, parameter, expected, found
+            ),
+            RuntimeError::ReturnType {
+                function,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Return type mismatch: {} should return {}, found {}",
+                function, expected, found
+            ),
+            RuntimeError::DuplicateParameter {
+                function,
+                parameter,
+            } => write!(
+                f,
+                "Duplicate parameter: {} names parameter {} more than once",
+                function, parameter
+            ),
+            RuntimeError::CyclicDefinition { names } if names.len() == 1 => write!(
+                f,
+                "Cyclic definition: cannot define {} in terms of itself",
+                names[0]
+            ),
+            RuntimeError::CyclicDefinition { names } => write!(
+                f,
+                "Cyclic definition: cannot define {} in terms of each other",
+                names.join(", ")
+            ),
+            RuntimeError::IntegerOverflow { operation } => write!(
+                f,
+                "Integer overflow: the result of '{}' does not fit in a SmallInt",
+                operation
+            ),
+            RuntimeError::NoMatchingGuard => {
+                write!(
+                    f,
+                    "No matching guard: every condition is false and there is no otherwise"
+                )
+            }
+            RuntimeError::NonExhaustiveMatch { value } => {
+                write!(f, "Non-exhaustive match: no arm matches {}", value)
+            }
+            RuntimeError::RangeTooLong { length, limit } => write!(
+                f,
+                "Range too long: {} elements, more than the limit of {}",
+                length, limit
+            ),
+            RuntimeError::GuardViolation { parameter } => {
+                write!(
+                    f,
+                    "Guard violation: parameter {} does not satisfy its guard",
+                    parameter
+                )
+            }
         }
     }
 }
+
+impl std::error::Error for RuntimeError {}
@@ -0,0 +1,120 @@
+mod common;
+
+use common::{eval, eval_error, parse};
+
+#[test]
+fn integer_division_truncates_toward_zero() {
+    assert_eq!(eval("7 // 2"), "3");
+    assert_eq!(eval("-7 // 2"), "-3");
+    assert_eq!(eval("7 // 2 == 3"), "true");
+    assert_eq!(eval_error("7 // 0"), "Division by zero");
+}
+
+#[test]
+fn multiplication_and_divisions_share_a_level() {
+    assert_eq!(parse("7 * 3 // 2"), "(7 * 3) // 2");
+    assert_eq!(parse("7 // 2 / 3"), "(7 // 2) / 3");
+    assert_eq!(parse("8 / 2 * 4"), "(8 / 2) * 4");
+    assert_eq!(parse("a * b / c // d"), "((a * b) / c) // d");
+    assert_eq!(eval("7 * 3 // 2"), "10");
+    assert_eq!(eval("7 // 2 / 3"), "1");
+    assert_eq!(eval("8 / 2 * 4"), "16");
+    assert_eq!(eval("20 // 3 * 3 / 2"), "9");
+}
+
+#[test]
+fn addition_and_subtraction_group_to_the_left() {
+    assert_eq!(parse("a - b + c"), "(a - b) + c");
+    assert_eq!(parse("a + b - c"), "(a + b) - c");
+    assert_eq!(eval("10 - 2 + 3"), "11");
+    assert_eq!(eval("1 + 2 * 3 - 4 // 3"), "6");
+}
+
+#[test]
+fn rationals_stay_exact() {
+    assert_eq!(eval("1 / 3"), "1/3");
+    assert_eq!(eval("type(1 / 3)"), "Rational");
+    assert_eq!(eval("1 / 3 + 1 / 6"), "1/2");
+    assert_eq!(eval("2 / 4 - 1 / 2"), "0");
+    assert_eq!(eval("type(1 / 3 * 3)"), "SmallInt");
+    assert_eq!(eval("-1 / 3"), "-1/3");
+    assert_eq!(eval("4 / -6"), "-2/3");
+    assert_eq!(eval("(2 / 3) ^ 2"), "4/9");
+    assert_eq!(eval("(2 / 3) ^ -2"), "9/4");
+    assert_eq!(eval("(7 / 2) // (1 / 3)"), "10");
+    assert_eq!(eval("abs(-5 / 7)"), "5/7");
+    assert_eq!(eval("[1 / 3 < 1 / 2, 2 / 3 == 4 / 6]"), "[true, true]");
+}
+
+#[test]
+fn rationals_decay_to_decimals_alongside_decimals() {
+    assert_eq!(eval("type(1 / 4 + 0.5)"), "Decimal");
+    assert_eq!(eval("1 / 4 + 0.5"), "0.75");
+    assert_eq!(eval_error("(1 / 2) / 0"), "Division by zero");
+    assert_eq!(
+        eval_error("(0 / 1 + 1 / 2 - 1 / 2) ^ -1"),
+        "Division by zero"
+    );
+}
+
+#[test]
+fn complex_numbers() {
+    assert_eq!(eval("(1 + 2i) * (3 - i)"), "5 + 5i");
+    assert_eq!(eval("sqrt(-4)"), "0 + 2i");
+    assert_eq!(eval("abs(3 + 4i)"), "5");
+}
+
+#[test]
+fn small_integers_promote_when_they_overflow() {
+    assert_eq!(eval("9223372036854775807 + 1"), "9223372036854775808");
+}
+
+#[test]
+fn type_mismatches_name_the_operator() {
+    assert_eq!(
+        eval_error("1 + sqrt"),
+        "Type mismatch: cannot apply '+' to SmallInt and Function"
+    );
+    assert_eq!(
+        eval_error("\"a\" * true"),
+        "Type mismatch: cannot apply '*' to Str and Bool"
+    );
+}
+
+#[test]
+fn dividing_by_zero_is_an_error_for_every_kind_of_number() {
+    for source in [
+        "1.0 / 0.0",
+        "1 / 0.0",
+        "1.0 / 0",
+        "1 / 0",
+        "1 // 0",
+        "1/2 / 0",
+    ] {
+        assert_eq!(eval_error(source), "Division by zero", "{}", source);
+    }
+}
+
+#[test]
+fn negating_the_smallest_integer_promotes() {
+    assert_eq!(eval("-(-9223372036854775807 - 1)"), "9223372036854775808");
+    assert_eq!(eval("-(9223372036854775807)"), "-9223372036854775807");
+}
+
+#[test]
+fn reals_print_without_negative_zeros_or_trailing_zeros() {
+    assert_eq!(eval("0.0"), "0");
+    assert_eq!(eval("-0.0"), "0");
+    assert_eq!(eval("0.0 * -1"), "0");
+    assert_eq!(eval("-0.5 + 0.5"), "0");
+    assert_eq!(eval("2.50"), "2.5");
+    assert_eq!(eval("1.5000000"), "1.5");
+    assert_eq!(eval("1200.000"), "1200");
+}
+
+#[test]
+fn integer_literals_too_big_for_a_small_int_become_decimals() {
+    assert_eq!(eval("99999999999999999999"), "99999999999999999999");
+    assert_eq!(eval("type(99999999999999999999)"), "Decimal");
+    assert_eq!(eval("type(9223372036854775807)"), "SmallInt");
+}
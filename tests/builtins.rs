@@ -0,0 +1,286 @@
+mod common;
+
+use common::{eval, eval_error, eval_error_with, eval_with};
+
+#[test]
+fn gcd_and_lcm_of_lists() {
+    assert_eq!(eval("gcdList([12, 18, 24])"), "6");
+    assert_eq!(eval("lcmList([2, 3, 4])"), "12");
+    assert_eq!(eval("gcdList([7])"), "7");
+    assert_eq!(eval("lcmList([-7])"), "7");
+}
+
+#[test]
+fn gcd_and_lcm_of_lists_with_zeros() {
+    assert_eq!(eval("gcdList([0, 0])"), "0");
+    assert_eq!(eval("gcdList([0, 6, -9])"), "3");
+    assert_eq!(eval("lcmList([0, 5])"), "0");
+}
+
+#[test]
+fn gcd_and_lcm_of_lists_reject_empty_and_non_integer_lists() {
+    assert_eq!(
+        eval_error("gcdList([])"),
+        "Invalid argument to gcdList: the list is empty"
+    );
+    assert_eq!(
+        eval_error("lcmList([])"),
+        "Invalid argument to lcmList: the list is empty"
+    );
+    assert_eq!(
+        eval_error("lcmList([2, 1.5])"),
+        "Invalid type: Decimal for operation lcmList"
+    );
+}
+
+#[test]
+fn weighted_mean() {
+    assert_eq!(
+        eval("weightedMean([1, 2, 3], [1, 2, 3])"),
+        "2.33333333333333333333333333333"
+    );
+    assert_eq!(eval("weightedMean([1, 2], [1/2, 1/2])"), "1.5");
+    assert_eq!(eval("type(weightedMean([2], [3]))"), "Decimal");
+}
+
+#[test]
+fn weighted_mean_rejects_bad_lists() {
+    assert_eq!(
+        eval_error("weightedMean([1, 2], [1, -1])"),
+        "Invalid argument to weightedMean: the weights add up to zero"
+    );
+    assert_eq!(
+        eval_error("weightedMean([1], [1, 2])"),
+        "Invalid argument to weightedMean: the lists have lengths 1 and 2"
+    );
+    assert_eq!(
+        eval_error("weightedMean([], [])"),
+        "Invalid argument to weightedMean: the lists are empty"
+    );
+    assert_eq!(
+        eval_error("weightedMean([1, \"a\"], [1, 1])"),
+        "Invalid type: Str for operation weightedMean"
+    );
+}
+
+#[test]
+fn reduce_right_folds_from_the_right() {
+    assert_eq!(eval("fold((acc, x) => acc - x, 0, [1, 2, 3])"), "-6");
+    assert_eq!(eval("reduceRight((x, acc) => x - acc, 0, [1, 2, 3])"), "2");
+    assert_eq!(
+        eval("reduceRight((x, acc) => [x, acc], [], [1, 2])"),
+        "[1, [2, []]]"
+    );
+    assert_eq!(eval("reduceRight((x, acc) => x + acc, 7, [])"), "7");
+}
+
+#[test]
+fn reduce_right_checks_its_arguments() {
+    assert_eq!(
+        eval_error("reduceRight((x) => x, 0, [1])"),
+        "Parameter mismatch: expected 1 arguments, found 2"
+    );
+    assert_eq!(
+        eval_error("reduceRight((x, acc) => x, 0, 5)"),
+        "Invalid type: SmallInt for operation reduceRight"
+    );
+}
+
+#[test]
+fn memoize_keeps_results_for_different_kinds_of_number_apart() {
+    let program = "g(x) = type(x)\nh(x) = x / 2\nk(l) = type(l[0])";
+    let memoized = "{ m = memoize(g); [m(1), m(1.0), m(1)] }";
+    assert_eq!(
+        common::eval_with(program, memoized),
+        "[SmallInt, Decimal, SmallInt]"
+    );
+    let memoized = "{ m = memoize(h); [m(1), m(1.0)] }";
+    assert_eq!(common::eval_with(program, memoized), "[1/2, 0.5]");
+    let memoized = "{ m = memoize(k); [m([1]), m([1.0])] }";
+    assert_eq!(common::eval_with(program, memoized), "[SmallInt, Decimal]");
+}
+
+#[test]
+fn assertions_return_what_they_check() {
+    assert_eq!(eval("assert(1 < 2)"), "true");
+    assert_eq!(eval("assert_eq(2 + 2, 4)"), "4");
+    assert_eq!(
+        eval_error("assert_eq(2 + 2, 5)"),
+        "Assertion failed: expected 5, found 4"
+    );
+}
+
+#[test]
+fn round_to_rounds_to_decimal_places() {
+    assert_eq!(eval("round_to(3.14159, 2)"), "3.14");
+    assert_eq!(eval("round_to(2.71828, 3)"), "2.718");
+    assert_eq!(eval("round_to(2.675, 1)"), "2.7");
+    assert_eq!(eval("round_to(2.5, 0)"), "3");
+    assert_eq!(eval("type(round_to(2, 0))"), "Decimal");
+}
+
+#[test]
+fn round_to_rejects_negative_digits() {
+    assert_eq!(
+        eval_error("round_to(3.14, -1)"),
+        "Invalid argument to round_to: the number of digits must not be negative"
+    );
+}
+
+#[test]
+fn round_to_rejects_too_many_digits() {
+    assert_eq!(eval("round_to(1.5, 1000)"), "1.5");
+    assert_eq!(
+        eval_error("round_to(1.5, 1000000000)"),
+        "Invalid argument to round_to: the number of digits must be at most 1000"
+    );
+}
+
+#[test]
+fn parity_and_divisibility() {
+    assert_eq!(
+        eval("[is_even(4), is_even(-3), is_odd(7), is_odd(0)]"),
+        "[true, false, true, false]"
+    );
+    assert_eq!(
+        eval("[divides(3, 12), divides(5, 12), divides(0, 0), divides(0, 4)]"),
+        "[true, false, true, false]"
+    );
+    assert_eq!(
+        eval_error("is_even(2.0)"),
+        "Invalid type: Decimal for operation is_even"
+    );
+}
+
+const FIB: &str = "fib(n) = n < 2 ? n : fib(n - 1) + fib(n - 2)";
+
+#[test]
+fn profile_counts_calls_to_each_function() {
+    assert_eq!(eval_with(FIB, "profile(() => fib(10))"), "[(fib, 177)]");
+    assert_eq!(
+        eval_with(
+            &format!("{}\nsquare(x) = x * x", FIB),
+            "profile(() => square(fib(3)) + square(1))"
+        ),
+        "[(fib, 5), (square, 2)]"
+    );
+}
+
+#[test]
+fn profile_counts_afresh_each_time() {
+    assert_eq!(
+        eval_with(FIB, "[profile(() => fib(2)), profile(() => fib(1))]"),
+        "[[(fib, 3)], [(fib, 1)]]"
+    );
+}
+
+#[test]
+fn profile_needs_a_function_without_parameters() {
+    assert_eq!(
+        eval_error_with(FIB, "profile(fib)"),
+        "Parameter mismatch: expected 1 arguments, found 0"
+    );
+    assert_eq!(
+        eval_error_with(FIB, "profile(() => fib(1) / 0)"),
+        "Division by zero"
+    );
+}
+
+#[test]
+fn min_max_and_ranges_of_values() {
+    assert_eq!(eval("min(3, 1, 2)"), "1");
+    assert_eq!(eval("clamp(5, 1, 3)"), "3");
+    assert_eq!(eval("inRange(3, 1, 3, true)"), "false");
+}
+
+#[test]
+fn roots_and_their_domains() {
+    assert_eq!(eval("cbrt(-8)"), "-2");
+    assert_eq!(eval("root(3, 27)"), "3");
+    assert_eq!(
+        eval_error("root(2, -4)"),
+        "Invalid argument to root: even root of a negative number"
+    );
+}
+
+#[test]
+fn trigonometry_checks_its_domain() {
+    assert_eq!(eval("sinh(0)"), "0");
+    assert_eq!(eval("cosh(0)"), "1");
+    assert_eq!(
+        eval_error("acos(2)"),
+        "Invalid argument to acos: 2 is outside the domain [-1, 1]"
+    );
+}
+
+#[test]
+fn ranges_can_step_down() {
+    assert_eq!(eval("range(10, 0, -3)"), "[10, 7, 4, 1]");
+    assert_eq!(
+        eval_error("range(0, 1, 0)"),
+        "Invalid argument to range: the step can't be zero"
+    );
+}
+
+#[test]
+fn min_and_max_take_any_number_of_arguments() {
+    assert_eq!(eval("max(3, 7, 5)"), "7");
+    assert_eq!(eval("max(2, 2.5)"), "2.5");
+    assert_eq!(eval("min(1/2, 0.25)"), "0.25");
+    assert_eq!(
+        eval_error("max(1)"),
+        "Parameter mismatch: expected 2 arguments, found 1"
+    );
+}
+
+#[test]
+fn map_and_filter_over_lists() {
+    assert_eq!(eval("map((x) => x * 2, [1, 2, 3])"), "[2, 4, 6]");
+    assert_eq!(eval("map(sqrt, [])"), "[]");
+    assert_eq!(eval("filter((x) => x > 1, [1, 2, 3])"), "[2, 3]");
+    assert_eq!(
+        eval_error("filter((x) => 1, [1])"),
+        "Invalid type: SmallInt for operation filter"
+    );
+}
+
+#[test]
+fn length_of_lists_and_strings() {
+    assert_eq!(eval("length(\"hello\")"), "5");
+    assert_eq!(eval("length([])"), "0");
+    assert_eq!(eval("length(range(0, 1000))"), "1000");
+    assert_eq!(
+        eval_error("length(5)"),
+        "Invalid type: SmallInt for operation length"
+    );
+}
+
+#[test]
+fn sum_and_product_of_lists() {
+    assert_eq!(eval("sum([1, 2, 3])"), "6");
+    assert_eq!(eval("product([2, 3])"), "6");
+    assert_eq!(
+        eval("product([9223372036854775807, 2])"),
+        "18446744073709551614"
+    );
+    assert_eq!(eval("sum([1, 2.5])"), "3.5");
+    assert_eq!(eval("sum([])"), "0");
+    assert_eq!(eval("product([])"), "1");
+    assert_eq!(
+        eval_error("sum([1, sqrt])"),
+        "Type mismatch: cannot apply 'sum' to SmallInt and Function"
+    );
+}
+
+#[test]
+fn gcd_and_lcm_of_two_integers() {
+    assert_eq!(eval("gcd(12, 18)"), "6");
+    assert_eq!(eval("gcd(-4, 6)"), "2");
+    assert_eq!(eval("gcd(0, 0)"), "0");
+    assert_eq!(eval("lcm(4, 6)"), "12");
+    assert_eq!(eval("lcm(0, 5)"), "0");
+    assert_eq!(
+        eval_error("gcd(1.5, 2)"),
+        "Invalid type: Decimal for operation gcd"
+    );
+}
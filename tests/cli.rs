@@ -0,0 +1,414 @@
+use std::{
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+fn aleph(arguments: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_aleph-language"))
+        .args(arguments)
+        .output()
+        .expect("the interpreter should run")
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn positional_arguments_follow_the_expression() {
+    assert_eq!(stdout(&aleph(&["-e", "$1 + $2", "3", "4"])), "7\n");
+    assert_eq!(stdout(&aleph(&["-e", "$1 * $2", "1.5", "-2"])), "-3\n");
+    assert_eq!(stdout(&aleph(&["-e", "$#", "a", "b", "c"])), "3\n");
+    assert_eq!(stdout(&aleph(&["-e", "$1 + \"!\"", "hi"])), "hi!\n");
+}
+
+#[test]
+fn missing_positional_arguments_are_unbound() {
+    let output = aleph(&["-e", "$1"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Unbound variable: $1"));
+}
+
+fn batch(input: &str) -> Output {
+    feed(
+        Command::new(env!("CARGO_BIN_EXE_aleph-language")).arg("--batch"),
+        input,
+    )
+}
+
+/// Runs `command`, writing `input` to its stdin.
+fn feed(command: &mut Command, input: &str) -> Output {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("the interpreter should run");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn brackets_in_line_comments_do_not_continue_the_line() {
+    assert_eq!(stdout(&batch("1 + 1 # has a ( paren\n2 + 2\n")), "2\n4\n");
+}
+
+#[test]
+fn semicolons_in_block_comments_do_not_split() {
+    assert_eq!(stdout(&batch("1 + 1 /* ; */\n")), "2\n");
+    assert_eq!(stdout(&batch("1 /* /* ; */ ; */ + 1; 3\n")), "2\n3\n");
+}
+
+#[test]
+fn unclosed_brackets_continue_onto_later_lines() {
+    assert_eq!(stdout(&batch("(1 +\n2)\n[1,\n2]\n")), "3\n[1, 2]\n");
+}
+
+#[test]
+fn block_comments_continue_onto_later_lines() {
+    assert_eq!(stdout(&batch("1 + /* a\nlong ( comment */ 2\n")), "3\n");
+}
+
+#[test]
+fn hashes_opening_raw_strings_are_not_comments() {
+    assert_eq!(stdout(&batch("r#\"a ( b\"# + \"!\"\n")), "a ( b!\n");
+}
+
+#[test]
+fn semicolons_separate_results() {
+    assert_eq!(stdout(&batch("1 + 1; 2 * 3; 9 // 2\n")), "2\n6\n4\n");
+}
+
+#[test]
+fn errors_do_not_stop_later_segments() {
+    let output = batch("1 / 0; 2 + 2\n");
+    assert_eq!(stdout(&output), "4\n");
+    assert!(stderr(&output).contains("Division by zero"));
+}
+
+#[test]
+fn comments_after_segments_are_ignored() {
+    assert_eq!(stdout(&batch("1; 2 # (\n3\n")), "1\n2\n3\n");
+}
+
+#[test]
+fn blank_lines_report_the_input_they_discard() {
+    let output = batch("(1 +\n\n5\n");
+    assert_eq!(stdout(&output), "5\n");
+    assert!(stderr(&output).contains("discarded unfinished input: (1 +"));
+}
+
+/// Writes `program` to a fresh file for the interpreter to load.
+fn program_file(name: &str, program: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("aleph-{}-{}.aleph", name, std::process::id()));
+    std::fs::write(&path, program).unwrap();
+    path
+}
+
+#[test]
+fn loading_a_file_does_not_dump_its_syntax_tree() {
+    let path = program_file("quiet", "square(x) = x * x\n");
+    let output = aleph(&[path.to_str().unwrap()]);
+    assert!(!stdout(&output).contains("FunctionDefinition"));
+}
+
+#[test]
+fn ast_json_describes_the_statements() {
+    let path = program_file("ast", "square(x) = x * x\n");
+    let json = stdout(&aleph(&["--ast-json", path.to_str().unwrap()]));
+    assert!(json.starts_with("[{\"kind\":\"FunctionDefinition\",\"name\":\"square\""));
+    assert!(json.contains("\"parameters\":[\"x\"]"));
+    assert!(json.contains("\"kind\":\"Multiply\""));
+}
+
+#[test]
+fn type_declarations_work_in_the_repl() {
+    let output = batch("f : Int -> Int\nf(x) = x + 1\nf(2)\nf(2.5)\n");
+    assert_eq!(stdout(&output), "3\n");
+    assert!(stderr(&output).contains("argument 1 (x) should be Int, found Decimal"));
+}
+
+#[test]
+fn expressions_can_start_with_definitions() {
+    let output = aleph(&["-e", "f : (Int, Int) -> Int; f(a, b) = a * b; f(3, 4)"]);
+    assert_eq!(stdout(&output), "12\n");
+    let output = aleph(&["-e", "f : Int -> Int; f(x) = x; f(0.5)"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn names_starting_with_where_do_not_start_a_where_clause() {
+    assert_eq!(stdout(&batch("where_to = 2; where_to + 1\n")), "3\n");
+}
+
+#[test]
+fn where_clauses_end_at_a_semicolon_not_followed_by_a_binding() {
+    assert_eq!(stdout(&batch("f(x) = y where y = x; f(2)\n")), "2\n");
+    assert_eq!(
+        stdout(&batch("g(x) = a + b where a = x; b = 2; g(1)\n")),
+        "3\n"
+    );
+}
+
+#[test]
+fn explain_shows_the_failing_operand() {
+    let output = aleph(&["--explain", "1 + (2 * sqrt)"]);
+    assert!(!output.status.success());
+    assert_eq!(
+        stdout(&output),
+        "2 * sqrt\n  2 : SmallInt\n  sqrt : Function\n"
+    );
+    assert!(stderr(&output).contains("cannot apply '*' to SmallInt and Function"));
+}
+
+#[test]
+fn explain_prints_values_that_succeed() {
+    let output = aleph(&["--explain", "1 + $1", "4"]);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "5\n");
+}
+
+/// Runs an interactive session with its home, and so its history file, in `home`.
+fn session(home: &std::path::Path, input: &str) -> Output {
+    feed(
+        Command::new(env!("CARGO_BIN_EXE_aleph-language")).env("HOME", home),
+        input,
+    )
+}
+
+#[test]
+fn history_is_kept_between_sessions() {
+    let home = std::env::temp_dir().join(format!("aleph-home-{}", std::process::id()));
+    std::fs::create_dir_all(&home).unwrap();
+    let file = home.join(".aleph_history");
+    let _ = std::fs::remove_file(&file);
+    assert_eq!(stdout(&session(&home, "1 + 1\n(2 +\n3)\n")), "2\n5\n\n");
+    let saved = std::fs::read_to_string(&file).unwrap();
+    assert!(saved.ends_with("1 + 1\n(2 + 3)\n"));
+    assert_eq!(
+        stdout(&session(&home, ":history\n")),
+        "    1  1 + 1\n    2  (2 + 3)\n    3  :history\n\n"
+    );
+    let saved = std::fs::read_to_string(&file).unwrap();
+    assert!(saved.ends_with("1 + 1\n(2 + 3)\n:history\n"));
+    std::fs::remove_dir_all(&home).unwrap();
+}
+
+#[test]
+fn history_size_limits_what_is_kept() {
+    let home = std::env::temp_dir().join(format!("aleph-small-home-{}", std::process::id()));
+    std::fs::create_dir_all(&home).unwrap();
+    let _ = std::fs::remove_file(home.join(".aleph_history"));
+    let output = feed(
+        Command::new(env!("CARGO_BIN_EXE_aleph-language"))
+            .env("HOME", &home)
+            .env("ALEPH_HISTORY_SIZE", "2"),
+        "1\n2\n3\n:history\n",
+    );
+    assert_eq!(stdout(&output), "1\n2\n3\n    1  3\n    2  :history\n\n");
+    std::fs::remove_dir_all(&home).unwrap();
+}
+
+#[test]
+fn display_settings_change_how_results_print() {
+    let output = batch("x = 1 / 3.0\nx\n:format 3\nx\n:frac\n0.75\n");
+    assert_eq!(
+        stdout(&output),
+        "0.333333333333333333333333333333\n0.333\n3/4\n"
+    );
+}
+
+#[test]
+fn overflow_modes() {
+    let output = batch(
+        ":overflow wrap\n9223372036854775807 + 1\n:overflow error\n9223372036854775807 + 1\n",
+    );
+    assert_eq!(stdout(&output), "-9223372036854775808\n");
+    assert!(
+        stderr(&output).contains("Integer overflow: the result of '+' does not fit in a SmallInt")
+    );
+}
+
+#[test]
+fn limits_can_be_set_in_the_repl() {
+    let output = batch("f(n) = f(n - 1)\n:limit 5\nf(1)\n:rangelimit 3\nrange(0, 10)\n");
+    let errors = stderr(&output);
+    assert!(errors.contains("Recursion limit exceeded: more than 5 nested calls"));
+    assert!(errors.contains("Range too long: 10 elements, more than the limit of 3"));
+}
+
+#[test]
+fn definitions_cannot_refer_to_themselves() {
+    let output = batch("x = x + 1\n");
+    assert!(stderr(&output).contains("Cyclic definition: cannot define x in terms of itself"));
+}
+
+#[test]
+fn underscore_is_the_last_result() {
+    assert_eq!(stdout(&batch("2 + 3\n_ * 2\n4;\n")), "5\n10\n");
+}
+
+#[test]
+fn redefining_a_builtin_warns_and_takes_effect() {
+    let output = batch("sqrt(x) = x + 1\nsqrt(4)\ni = 2\ni * 3\n");
+    assert_eq!(stdout(&output), "5\n6\n");
+    let errors = stderr(&output);
+    assert!(errors.contains("warning: definition shadows the builtin sqrt"));
+    assert!(errors.contains("warning: definition shadows the builtin i"));
+}
+
+#[test]
+fn definitions_in_the_repl_can_be_used_and_replaced() {
+    let output = batch("square(x) = x * x\nsquare(9)\nsquare(x) = x + 1\nsquare(9)\n");
+    assert_eq!(stdout(&output), "81\n10\n");
+    assert_eq!(stderr(&output), "");
+}
+
+#[test]
+fn load_defines_a_file_in_the_running_repl() {
+    let path = program_file("load", "double(x) = x * 2\n");
+    let output = batch(&format!(":load {}\ndouble(4)\n", path.display()));
+    assert_eq!(
+        stdout(&output),
+        format!("loaded 1 definitions from {}\n8\n", path.display())
+    );
+}
+
+#[test]
+fn vars_lists_the_bindings() {
+    let output = batch("square(x) = x * x\ny = 3\n:vars\n");
+    assert_eq!(stdout(&output), "i = 0 + 1i\nsquare(x)\ny = 3\n");
+}
+
+#[test]
+fn end_of_input_ends_the_session_cleanly() {
+    let home = std::env::temp_dir().join(format!("aleph-eof-home-{}", std::process::id()));
+    std::fs::create_dir_all(&home).unwrap();
+    let output = session(&home, "");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "\n");
+    let output = session(&home, "\n\n1 + 1\n");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "2\n\n");
+    assert_eq!(stderr(&output), "");
+    std::fs::remove_dir_all(&home).unwrap();
+}
+
+#[test]
+fn long_results_are_truncated_until_full_is_asked_for() {
+    let output = batch("range(1, 100)\nx = 2 ^ 400\nx\n:full\nrange(1, 100)\nx\n");
+    let out = stdout(&output);
+    let lines: Vec<&str> = out.lines().collect();
+    assert!(lines[0].starts_with("[1, 2, 3, "));
+    assert!(lines[0].ends_with(", 50, ..., 99 elements]"));
+    assert!(lines[1].ends_with("... (121 digits)"));
+    assert!(lines[2].ends_with(", 98, 99]"));
+    assert_eq!(lines[3].len(), 121);
+}
+
+#[test]
+fn sci_switches_very_large_and_small_reals_to_exponents() {
+    let output = batch(":sci\n1.5e20\n123456.0\n0.00001\n:sci 3 3\n1234.5\n0.01\n");
+    assert_eq!(stdout(&output), "1.5e20\n123456\n1e-5\n1.2345e3\n1e-2\n");
+}
+
+#[test]
+fn loading_warns_about_unbound_variables() {
+    let path = program_file("unbound", "good(x) = x\nbad(x) = x + missing\n");
+    let output = aleph(&[path.to_str().unwrap()]);
+    assert_eq!(
+        stderr(&output),
+        "warning: bad refers to unbound variable missing at line 2, column 14\n"
+    );
+    let path = program_file("bound", "good(x) = x + other(x)\nother(x) = x\n");
+    assert_eq!(stderr(&aleph(&[path.to_str().unwrap()])), "");
+}
+
+#[test]
+fn rounding_modes_change_inexact_results() {
+    let output = batch(":rounding up\n2 / 3.0\n:rounding down\n2 / 3.0\n");
+    assert_eq!(
+        stdout(&output),
+        "0.666666666666666666666666666667\n0.666666666666666666666666666666\n"
+    );
+}
+
+#[test]
+fn check_reports_every_problem_without_running() {
+    let clean = program_file("clean", "double(x) = x * 2\n");
+    let output = aleph(&["--check", clean.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(stderr(&output), "");
+    let broken = program_file(
+        "broken",
+        "bad(x) = x + missing\nworse(y) = sqrt(1, 2)\nboom = print(1)\n",
+    );
+    let output = aleph(&["--check", broken.to_str().unwrap()]);
+    assert!(!output.status.success());
+    assert_eq!(stdout(&output), "");
+    let errors = stderr(&output);
+    assert!(errors.contains("line 1, column 14: bad refers to unbound variable missing"));
+    assert!(errors
+        .contains("line 2, column 12: sqrt: Parameter mismatch: expected 1 arguments, found 2"));
+}
+
+#[test]
+fn trace_shows_operands_before_what_uses_them() {
+    let output = batch(":trace on\n2 + 3 * 4\n:trace off\n1 + 1\n");
+    assert_eq!(stdout(&output), "14\n2\n");
+    assert_eq!(
+        stderr(&output),
+        "  2 = 2\n    3 = 3\n    4 = 4\n  3 * 4 = 12\n2 + (3 * 4) = 14\n"
+    );
+}
+
+#[test]
+fn help_describes_the_builtins() {
+    let sqrt =
+        "sqrt (1 argument): The principal square root, which is Complex for negative numbers.\n";
+    assert!(stdout(&batch(":help\n")).contains(sqrt));
+    assert_eq!(stdout(&batch(":help sqrt\n")), sqrt);
+    assert_eq!(
+        stderr(&batch(":help nope\n")),
+        "error: no builtin named nope\n"
+    );
+}
+
+#[test]
+fn duplicate_parameters_are_errors_and_shadowed_globals_warnings() {
+    let output = batch("g(x, x) = x\ny = 3\nf(x, y) = x + y\nf(1, 2)\n");
+    assert_eq!(stdout(&output), "3\n");
+    assert_eq!(
+        stderr(&output),
+        "error: Duplicate parameter: g names parameter x more than once\n\
+         warning: parameter y of f shadows the global y\n"
+    );
+}
+
+#[test]
+fn print_writes_its_argument_and_returns_it() {
+    let output = aleph(&["-e", "print(5) + 1"]);
+    assert_eq!(stdout(&output), "5\n6\n");
+}
+
+#[test]
+fn unreachable_match_arms_are_flagged() {
+    let output = batch(
+        "w(x) = match x { _ => 0, 1 => 1 }\nd(x) = match x { 1 => 1, 1 => 2, _ => 3 }\nd(1)\n",
+    );
+    assert_eq!(stdout(&output), "1\n");
+    assert_eq!(
+        stderr(&output),
+        "warning: match arm 1 in w can never be chosen: it follows _\n\
+         warning: match arm 1 in d can never be chosen: an earlier arm has the same pattern\n"
+    );
+}
@@ -0,0 +1,44 @@
+#![allow(dead_code)]
+
+use aleph_language::{AlephError, Interpreter};
+
+/// Evaluates `source` in a fresh interpreter and shows the result as the REPL would.
+pub fn eval(source: &str) -> String {
+    eval_with("", source)
+}
+
+/// Defines `program` in a fresh interpreter, then evaluates `source` and shows the result.
+pub fn eval_with(program: &str, source: &str) -> String {
+    let mut interpreter = Interpreter::new();
+    interpreter.define(program).expect("program should define");
+    let value = interpreter
+        .eval(source)
+        .unwrap_or_else(|e| panic!("{} failed: {}", source, e));
+    value.display(&interpreter.context.display).to_string()
+}
+
+/// Evaluates `source` in a fresh interpreter, expecting it to fail, and shows the error.
+pub fn eval_error(source: &str) -> String {
+    eval_error_with("", source)
+}
+
+pub fn eval_error_with(program: &str, source: &str) -> String {
+    let mut interpreter = Interpreter::new();
+    interpreter.define(program).expect("program should define");
+    match interpreter.eval(source) {
+        Ok(value) => panic!(
+            "{} should fail but gave {}",
+            source,
+            value.display(&interpreter.context.display)
+        ),
+        Err(AlephError::Runtime(error)) => error.to_string(),
+        Err(error) => panic!("{} should fail at runtime, not with {}", source, error),
+    }
+}
+
+/// Parses `source` as an expression and shows it fully bracketed.
+pub fn parse(source: &str) -> String {
+    aleph_language::parser::parse_complete_expression(source)
+        .unwrap_or_else(|e| panic!("{} failed to parse: {}", source, e))
+        .to_string()
+}
@@ -0,0 +1,23 @@
+mod common;
+
+use common::{eval, eval_error_with, eval_with};
+
+#[test]
+fn functions_given_fewer_arguments_wait_for_the_rest() {
+    let program = "add3(a, b, c) = a + b + c";
+    assert_eq!(eval_with(program, "add3(1)(2)(3)"), "6");
+    assert_eq!(eval_with(program, "add3(1, 2)(3)"), "6");
+    assert_eq!(eval_with(program, "map(add3(1, 2), [1, 2])"), "[4, 5]");
+    assert_eq!(
+        eval_error_with(program, "add3(1, 2, 3, 4)"),
+        "Parameter mismatch: expected 3 arguments, found 4"
+    );
+}
+
+#[test]
+fn functions_show_their_signatures() {
+    assert_eq!(eval_with("f(x, y) = x + y", "f"), "<function f(x, y)>");
+    assert_eq!(eval_with("f(x, y) = x + y", "f(1)"), "<function f(y)>");
+    assert_eq!(eval("(a, b) => a"), "<lambda(a, b)>");
+    assert_eq!(eval("sqrt"), "<function sqrt>");
+}
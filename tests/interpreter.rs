@@ -0,0 +1,132 @@
+use std::collections::BTreeMap;
+
+use aleph_language::{
+    parser::{SyntaxError, TopLevelStatement},
+    value::{EvalContext, NativeFunction, RuntimeError, Value},
+    AlephError, Interpreter,
+};
+
+#[test]
+fn define_incrementally_reports_each_statement() {
+    let mut interpreter = Interpreter::new();
+    let source =
+        "x = 2\nsquare(n) = n * n\ny = square(x)\nz = )\nw = 1 / 0\nsquare(y) + 1\nv = y + 1\n";
+    let outcomes: Vec<_> = interpreter.define_incrementally(source).collect();
+    assert_eq!(outcomes.len(), 7);
+    assert!(matches!(
+        &outcomes[0],
+        Ok((TopLevelStatement::VariableDefinition { name, .. }, Ok(Some(value))))
+            if name == "x" && value.to_string() == "2"
+    ));
+    assert!(matches!(
+        &outcomes[1],
+        Ok((TopLevelStatement::FunctionDefinition { name, .. }, Ok(None))) if name == "square"
+    ));
+    assert!(matches!(
+        &outcomes[2],
+        Ok((TopLevelStatement::VariableDefinition { name, .. }, Ok(Some(value))))
+            if name == "y" && value.to_string() == "4"
+    ));
+    assert!(matches!(&outcomes[3], Err(SyntaxError { line: 4, .. })));
+    assert!(matches!(
+        &outcomes[4],
+        Ok((TopLevelStatement::VariableDefinition { name, .. }, Err(AlephError::Runtime(_))))
+            if name == "w"
+    ));
+    assert!(matches!(
+        &outcomes[5],
+        Ok((TopLevelStatement::Expression(_), Ok(Some(value)))) if value.to_string() == "17"
+    ));
+    assert!(matches!(
+        &outcomes[6],
+        Ok((TopLevelStatement::VariableDefinition { name, .. }, Ok(Some(value))))
+            if name == "v" && value.to_string() == "5"
+    ));
+}
+
+#[test]
+fn define_incrementally_evaluates_expressions_as_it_goes() {
+    let mut interpreter = Interpreter::new();
+    let outcomes: Vec<_> = interpreter
+        .define_incrementally("a = 1\na == 1\na = 3\na * 2\n")
+        .map(|outcome| match outcome {
+            Ok((TopLevelStatement::Expression(_), Ok(Some(value)))) => Some(value.to_string()),
+            Ok((_, Ok(_))) => None,
+            other => panic!("unexpected {:?}", other),
+        })
+        .collect();
+    assert_eq!(
+        outcomes,
+        [None, Some("true".to_string()), None, Some("6".to_string())]
+    );
+}
+
+#[test]
+fn define_incrementally_defines_as_it_goes() {
+    let mut interpreter = Interpreter::new();
+    let mut outcomes = interpreter.define_incrementally("a = 1\nb = 2\n");
+    assert!(outcomes.next().unwrap().is_ok());
+    drop(outcomes);
+    assert!(interpreter.get("a").is_some());
+    assert!(interpreter.get("b").is_none());
+}
+
+fn double(
+    _: &mut EvalContext,
+    _: &BTreeMap<String, Value>,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    match &arguments[0] {
+        Value::SmallInt(n) => Ok(Value::SmallInt(n * 2)),
+        other => Err(RuntimeError::InvalidType {
+            found: other.type_name(),
+            operation: "double".to_string(),
+        }),
+    }
+}
+
+fn registered_interpreter() -> Interpreter {
+    let mut interpreter = Interpreter::new();
+    interpreter.register(NativeFunction {
+        name: "double",
+        description: "double(n) is twice n.",
+        minimum_arguments: 1,
+        maximum_arguments: Some(1),
+        function: double,
+    });
+    interpreter
+}
+
+fn shown(interpreter: &mut Interpreter, source: &str) -> String {
+    let value = interpreter.eval(source).unwrap();
+    value.display(&interpreter.context.display).to_string()
+}
+
+#[test]
+fn registered_native_functions_are_called_like_any_other() {
+    let mut interpreter = registered_interpreter();
+    assert_eq!(shown(&mut interpreter, "double(21)"), "42");
+    assert_eq!(
+        shown(&mut interpreter, "map(double, [1, 2, 3])"),
+        "[2, 4, 6]"
+    );
+    interpreter
+        .define("quadruple(n) = double(double(n))")
+        .unwrap();
+    assert_eq!(shown(&mut interpreter, "quadruple(5)"), "20");
+    assert_eq!(shown(&mut interpreter, "type(double)"), "Function");
+    assert_eq!(shown(&mut interpreter, "double"), "<function double>");
+}
+
+#[test]
+fn registered_native_functions_check_their_arguments() {
+    let mut interpreter = registered_interpreter();
+    assert_eq!(
+        interpreter.eval("double(1, 2)").unwrap_err().to_string(),
+        "Parameter mismatch: expected 1 arguments, found 2"
+    );
+    assert_eq!(
+        interpreter.eval("double(\"a\")").unwrap_err().to_string(),
+        "Invalid type: Str for operation double"
+    );
+}
@@ -0,0 +1,26 @@
+mod common;
+
+use common::{eval, eval_with};
+
+#[test]
+fn names_can_contain_underscores() {
+    assert_eq!(
+        eval_with(
+            "my_value = 3\nsquare_of(x_1) = x_1 * x_1",
+            "square_of(my_value)"
+        ),
+        "9"
+    );
+}
+
+#[test]
+fn keywords_do_not_run_into_names_with_underscores() {
+    assert_eq!(eval_with("and_more = 1", "and_more + 1"), "2");
+    assert_eq!(eval("{ where_to = 2; where_to * 3 }"), "6");
+}
+
+#[test]
+fn a_bare_underscore_is_still_ignored() {
+    assert_eq!(eval_with("first(x, _) = x", "first(1, 2)"), "1");
+    assert_eq!(eval("match 5 { 1 => 0, _ => 1 }"), "1");
+}
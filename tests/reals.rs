@@ -0,0 +1,50 @@
+use aleph_language::{builtins::is_builtin, value::reals_created, Interpreter};
+
+/// Evaluates `source` and returns how many SmallInts were promoted to Decimals on the way.
+fn reals_created_by(interpreter: &mut Interpreter, source: &str) -> usize {
+    let before = reals_created();
+    interpreter
+        .eval(source)
+        .unwrap_or_else(|e| panic!("{} failed: {}", source, e));
+    reals_created() - before
+}
+
+#[test]
+fn integer_workloads_build_no_reals() {
+    let mut interpreter = Interpreter::new();
+    interpreter
+        .define("step(total, x) = total + x * x - (-x) ^ 3 // 7 + (-2) ^ 5 + 12 / 4")
+        .unwrap();
+    let workload = "fold(step, 0, range(1, 1000))";
+    assert_eq!(reals_created_by(&mut interpreter, workload), 0);
+    let total = interpreter.eval(workload).unwrap();
+    assert_eq!(total.type_name(), "SmallInt");
+}
+
+#[test]
+fn negative_bases_with_positive_exponents_stay_integers() {
+    let mut interpreter = Interpreter::new();
+    assert_eq!(reals_created_by(&mut interpreter, "(-2) ^ 3"), 0);
+    assert_eq!(
+        interpreter.eval("type((-2) ^ 3)").unwrap().to_string(),
+        "SmallInt"
+    );
+    assert_eq!(reals_created_by(&mut interpreter, "7 / 2 + 1 / 2"), 0);
+}
+
+#[test]
+fn mixed_arithmetic_still_promotes() {
+    let mut interpreter = Interpreter::new();
+    assert!(reals_created_by(&mut interpreter, "1 + 0.5") > 0);
+    assert!(reals_created_by(&mut interpreter, "2 ^ -1") > 0);
+    assert!(reals_created_by(&mut interpreter, "(-3) ^ 41") > 0);
+}
+
+#[test]
+fn checking_for_builtins_builds_no_reals() {
+    let before = reals_created();
+    assert!(is_builtin("i"));
+    assert!(is_builtin("sqrt"));
+    assert!(!is_builtin("square"));
+    assert_eq!(reals_created() - before, 0);
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use aleph_language::Interpreter;
+use common::{eval_error_with, eval_with};
+
+#[test]
+fn recursion_limit_is_an_error_on_a_small_stack() {
+    let result = std::thread::Builder::new()
+        .stack_size(256 * 1024)
+        .spawn(|| eval_error_with("forever(x) = forever(x)", "forever(1)"))
+        .unwrap()
+        .join()
+        .unwrap();
+    assert_eq!(
+        result,
+        "Recursion limit exceeded: more than 1000 nested calls"
+    );
+}
+
+#[test]
+fn recursion_up_to_the_limit_succeeds() {
+    assert_eq!(
+        eval_with("count(n) = n == 0 ? 0 : 1 + count(n - 1)", "count(999)"),
+        "999"
+    );
+}
+
+#[test]
+fn memoized_fib_matches_the_plain_one() {
+    let mut interpreter = Interpreter::new();
+    interpreter
+        .define("fib(n) = n < 2 ? n : fib(n - 1) + fib(n - 2)\nslow(n) = n < 2 ? n : slow(n - 1) + slow(n - 2)")
+        .unwrap();
+    interpreter.define("fib = memoize(fib)").unwrap();
+    let fast = interpreter.eval("fib(20)").unwrap();
+    let slow = interpreter.eval("slow(20)").unwrap();
+    assert_eq!(fast, slow);
+    let fib = interpreter.eval("fib(40)").unwrap();
+    assert_eq!(
+        fib.display(&interpreter.context.display).to_string(),
+        "102334155"
+    );
+}
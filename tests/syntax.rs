@@ -0,0 +1,250 @@
+mod common;
+
+use aleph_language::{
+    parser::{parse_complete_expression, Span},
+    AlephError, Interpreter,
+};
+use common::{eval, eval_error, eval_error_with, eval_with, parse};
+
+#[test]
+fn comments_are_skipped() {
+    assert_eq!(eval("1 + # the rest of the line\n2"), "3");
+    assert_eq!(eval("1 /* inline */ + 2"), "3");
+    assert_eq!(eval("1 /* outer /* inner */ still outer */ + 2"), "3");
+    assert_eq!(
+        eval_with("# leading comment\nf(x) = x /* trailing */", "f(4)"),
+        "4"
+    );
+}
+
+#[test]
+fn shebang_lines_are_ignored() {
+    assert_eq!(eval_with("#!/usr/bin/env aleph\nx = 2", "x"), "2");
+}
+
+#[test]
+fn factorial_is_postfix_and_binds_tightly() {
+    assert_eq!(eval("0!"), "1");
+    assert_eq!(eval("5!"), "120");
+    assert_eq!(eval("20!"), "2432902008176640000");
+    assert_eq!(eval("2 * 3!"), "12");
+    assert_eq!(eval("2 ^ 3!"), "64");
+    assert_eq!(eval("-3!"), "-6");
+    assert_eq!(
+        eval_error("(-1)!"),
+        "Invalid argument to !: factorial of a negative number"
+    );
+}
+
+#[test]
+fn numbers_written_against_names_or_brackets_multiply() {
+    assert_eq!(eval_with("x = 4", "2x"), "8");
+    assert_eq!(eval_with("x = 4", "-2x"), "-8");
+    assert_eq!(eval("2(3 + 1)"), "8");
+    assert_eq!(eval("3(4)"), "12");
+}
+
+#[test]
+fn unary_minus_binds_more_loosely_than_powers() {
+    assert_eq!(eval("-2^2"), "-4");
+    assert_eq!(eval("(-2)^2"), "4");
+    assert_eq!(eval("--3"), "3");
+    assert_eq!(parse("-a ^ b"), "-(a ^ b)");
+}
+
+#[test]
+fn powers_group_to_the_right() {
+    assert_eq!(eval("2 ^ 3 ^ 2"), "512");
+    assert_eq!(eval("2 ** 3 ** 2"), "512");
+    assert_eq!(eval("2 ** 10"), "1024");
+}
+
+#[test]
+fn percent_literals_divide_by_a_hundred() {
+    assert_eq!(eval("50%"), "0.5");
+    assert_eq!(eval("150%"), "1.5");
+    assert_eq!(eval("10% * 50"), "5");
+}
+
+#[test]
+fn bars_take_the_absolute_value() {
+    assert_eq!(eval("|-3|"), "3");
+    assert_eq!(eval("|1 - 4| + 1"), "4");
+    assert_eq!(eval("|-2| ^ 2"), "4");
+}
+
+#[test]
+fn conditionals_choose_a_branch() {
+    assert_eq!(eval("true ? 1 : 2"), "1");
+    assert_eq!(eval("1 < 2 ? \"a\" : \"b\""), "a");
+    assert_eq!(eval("false ? 1 : true ? 2 : 3"), "2");
+}
+
+#[test]
+fn comparisons_chain() {
+    assert_eq!(eval("1 < 2 < 3"), "true");
+    assert_eq!(eval("3 < 2 < 4"), "false");
+    assert_eq!(eval("{ x = 5; 1 < x <= 10 }"), "true");
+    assert_eq!(eval("[3 != 4, 1 >= 1, 2 > 3]"), "[true, true, false]");
+}
+
+#[test]
+fn bitwise_operators_work_on_integers() {
+    assert_eq!(eval("5 & 3"), "1");
+    assert_eq!(eval("5 | 3"), "7");
+    assert_eq!(eval("5 xor 3"), "6");
+    assert_eq!(eval("1 << 4"), "16");
+    assert_eq!(eval("8 >> 2"), "2");
+    assert_eq!(eval("true and false"), "false");
+}
+
+#[test]
+fn names_can_be_unicode() {
+    assert_eq!(eval_with("α = 2\nβ2 = 3", "α * β2"), "6");
+}
+
+#[test]
+fn lists_and_tuples() {
+    assert_eq!(eval("[1, 2, 3][1]"), "2");
+    assert_eq!(eval("[[1], []]"), "[[1], []]");
+    assert_eq!(eval("(1, (2, 3))"), "(1, (2, 3))");
+    assert_eq!(
+        eval_error("[1, 2, 3][3]"),
+        "Index out of range: 3 for a list of length 3"
+    );
+}
+
+#[test]
+fn strings_and_raw_strings() {
+    assert_eq!(eval("\"ab\" + \"cd\""), "abcd");
+    assert_eq!(eval("\"quote\\\"q\""), "quote\"q");
+    assert_eq!(eval("r#\"a \"quoted\" b\"#"), "a \"quoted\" b");
+    assert_eq!(eval("r##\"a\"#b\"##"), "a\"#b");
+}
+
+#[test]
+fn blocks_bind_in_sequence() {
+    assert_eq!(eval("{ a = 1; b = a + 1; a * b }"), "2");
+    assert_eq!(eval("{ a = 1; a = a + 1; a }"), "2");
+    assert_eq!(eval("{ (a, b) = (1, 2); a + b }"), "3");
+}
+
+#[test]
+fn where_clauses_bind_locals() {
+    assert_eq!(eval_with("h(n) = n * k where k = 2", "h(4)"), "8");
+}
+
+#[test]
+fn match_chooses_the_first_matching_arm() {
+    assert_eq!(eval("match 2 { 1 => 10, 2 => 20, _ => 0 }"), "20");
+    assert_eq!(eval("match -1 { -1 => \"neg\", _ => \"other\" }"), "neg");
+    assert_eq!(
+        eval_error("match 3 { 1 => 1, 2 => 2 }"),
+        "Non-exhaustive match: no arm matches 3"
+    );
+}
+
+#[test]
+fn guarded_definitions_take_the_first_true_branch() {
+    let program = "a(x) = x if x >= 0 | -x otherwise\ns(x) = 1 if x > 0 | -1 if x < 0";
+    assert_eq!(eval_with(program, "[a(-3), a(2), s(-5)]"), "[3, 2, -1]");
+    assert_eq!(
+        eval_error_with(program, "s(0)"),
+        "No matching guard: every condition is false and there is no otherwise"
+    );
+}
+
+#[test]
+fn lambdas_and_their_guards() {
+    assert_eq!(eval("((a) => a + 1)(2)"), "3");
+    assert_eq!(eval("((_, y) => y)(1, 2)"), "2");
+    assert_eq!(eval("((x where x > 0) => x)(4)"), "4");
+    assert_eq!(
+        eval_error("((x where x > 0) => x)(-1)"),
+        "Guard violation: parameter x does not satisfy its guard"
+    );
+}
+
+#[test]
+fn lambda_guards_need_where_as_a_whole_word() {
+    let mut interpreter = Interpreter::new();
+    assert!(matches!(
+        interpreter.eval("((x wherey > 0) => x)(1)"),
+        Err(AlephError::ParseError { .. })
+    ));
+}
+
+#[test]
+fn lambdas_keep_the_parameters_of_the_function_that_made_them() {
+    let program = "adder(n) = (x) => x + n";
+    assert_eq!(eval_with(program, "adder(2)(3)"), "5");
+    assert_eq!(eval_with(program, "map(adder(10), [1, 2])"), "[11, 12]");
+}
+
+#[test]
+fn operator_sections_are_functions() {
+    assert_eq!(eval("(+)(1, 2)"), "3");
+    assert_eq!(eval("(-)(5, 3)"), "2");
+    assert_eq!(eval("(/)(1, 2)"), "1/2");
+    assert_eq!(eval("fold((+), 0, [1, 2, 3])"), "6");
+}
+
+#[test]
+fn ignored_parameters_take_any_argument() {
+    assert_eq!(eval_with("second(_, y, _) = y", "second(1, 2, 3)"), "2");
+}
+
+#[test]
+fn only_functions_can_be_applied() {
+    assert_eq!(
+        eval_error("(1 + 1)(2)"),
+        "Invalid type: SmallInt for operation apply"
+    );
+}
+
+#[test]
+fn operator_precedence_is_unchanged() {
+    assert_eq!(parse("1 + 2 * 3"), "1 + (2 * 3)");
+    assert_eq!(parse("a < b and c"), "(a < b) and c");
+    assert_eq!(parse("1 << 2 + 3"), "1 << (2 + 3)");
+    assert_eq!(parse("a | b & c"), "a | (b & c)");
+    assert_eq!(parse("a xor b | c"), "(a xor b) | c");
+}
+
+#[test]
+fn parse_errors_have_a_line_and_column() {
+    let mut interpreter = Interpreter::new();
+    match interpreter.define("x = 1\ny = (2 +\nz = 3") {
+        Err(AlephError::ParseError { line, .. }) => assert_eq!(line, 2),
+        other => panic!("expected a parse error, found {:?}", other),
+    }
+    match interpreter.eval("1 2") {
+        Err(AlephError::ParseError {
+            line,
+            column,
+            message,
+        }) => {
+            assert_eq!((line, column), (1, 3));
+            assert_eq!(message, "unexpected '2'");
+        }
+        other => panic!("expected a parse error, found {:?}", other),
+    }
+}
+
+#[test]
+fn variables_know_where_they_were_written() {
+    let expression = parse_complete_expression("1 +\n  total").unwrap();
+    assert_eq!(
+        expression.free_variable_spans()["total"],
+        Some(Span {
+            start: 6,
+            end: 11,
+            line: 2,
+            column: 3
+        })
+    );
+    assert_eq!(
+        eval_error("1 +\n  missing"),
+        "Unbound variable: missing at line 2, column 3"
+    );
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use aleph_language::parser::{parse_complete_definition, TopLevelStatement};
+use common::{eval_error_with, eval_with};
+
+#[test]
+fn argument_positions_count_from_the_first_parameter_after_partial_application() {
+    let program = "k : (Int, Int) -> Int\nk(a, b) = a + b";
+    assert_eq!(
+        eval_error_with(program, "k(1)(2.5)"),
+        "Argument type mismatch: argument 2 (b) should be Int, found Decimal"
+    );
+    assert_eq!(
+        eval_error_with(program, "k(1.5)"),
+        "Argument type mismatch: argument 1 (a) should be Int, found Decimal"
+    );
+}
+
+#[test]
+fn return_type_mismatches_name_the_type_found() {
+    assert_eq!(
+        eval_error_with("name : Int -> Int\nname(n) = \"n\"", "name(1)"),
+        "Return type mismatch: name should return Int, found Str"
+    );
+}
+
+#[test]
+fn integer_results_are_promoted_to_declared_reals() {
+    let program = "f : Int -> Real\nf(n) = n * 2\nh : Int -> Real\nh(n) = n / 2";
+    assert_eq!(eval_with(program, "f(3)"), "6");
+    assert_eq!(eval_with(program, "type(f(3))"), "Decimal");
+    assert_eq!(eval_with(program, "type(h(1))"), "Decimal");
+}
+
+#[test]
+fn whole_reals_become_declared_integers_and_others_are_errors() {
+    let program = "g : Int -> Int\ng(n) = n / 2.0";
+    assert_eq!(eval_with(program, "type(g(4))"), "SmallInt");
+    assert_eq!(
+        eval_error_with(program, "g(3)"),
+        "Return type mismatch: g should return Int, found Decimal"
+    );
+}
+
+#[test]
+fn tuples_match_product_types_element_by_element() {
+    let program = "swap : (Int, Int) -> (Int, Int)\nswap(a, b) = (b, a)";
+    assert_eq!(eval_with(program, "swap(1, 2)"), "(2, 1)");
+    assert_eq!(
+        eval_error_with("pair : Int -> (Int, Str)\npair(n) = (n, n)", "pair(1)"),
+        "Return type mismatch: pair should return (Int, Str), found Tuple"
+    );
+    assert_eq!(
+        eval_error_with(
+            "triple : Int -> (Int, Int)\ntriple(n) = (n, n, n)",
+            "triple(1)"
+        ),
+        "Return type mismatch: triple should return (Int, Int), found Tuple"
+    );
+}
+
+#[test]
+fn single_statements_can_be_type_declarations() {
+    let statement = parse_complete_definition("f : (Int, Int) -> Int # adds").unwrap();
+    assert!(matches!(
+        statement,
+        TopLevelStatement::FunctionTypeDeclaration { ref name, .. } if name == "f"
+    ));
+    assert!(parse_complete_definition("f : Int -> Int g").is_err());
+}
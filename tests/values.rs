@@ -0,0 +1,52 @@
+use aleph_language::{value::Value, Interpreter};
+
+fn values(sources: &[&str]) -> Vec<Value> {
+    let mut interpreter = Interpreter::new();
+    interpreter.define("f(x) = x\ng(x) = x").unwrap();
+    sources
+        .iter()
+        .map(|source| interpreter.eval(source).unwrap())
+        .collect()
+}
+
+#[test]
+fn numbers_are_equal_by_value_whatever_their_type() {
+    let numbers = values(&["1", "1.0", "2/2", "1 + 0i", "1/2", "0.5"]);
+    assert_eq!(numbers[0], numbers[1]);
+    assert_eq!(numbers[0], numbers[2]);
+    assert_eq!(numbers[0], numbers[3]);
+    assert_eq!(numbers[4], numbers[5]);
+    assert_ne!(numbers[0], numbers[4]);
+}
+
+#[test]
+fn functions_are_equal_only_to_themselves() {
+    let functions = values(&["f", "f", "g", "sqrt", "sqrt", "abs"]);
+    assert_eq!(functions[0], functions[1]);
+    assert_ne!(functions[0], functions[2]);
+    assert_eq!(functions[3], functions[4]);
+    assert_ne!(functions[3], functions[5]);
+}
+
+#[test]
+fn mixed_values_sort_by_kind_then_value() {
+    let mut mixed = values(&[
+        "\"b\"", "2.5", "f", "true", "[1]", "1/2", "\"a\"", "sqrt", "3",
+    ]);
+    mixed.sort();
+    let shown: Vec<String> = mixed.iter().map(Value::to_string).collect();
+    assert_eq!(
+        shown,
+        [
+            "true",
+            "1/2",
+            "2.5",
+            "3",
+            "a",
+            "b",
+            "[1]",
+            "<function sqrt>",
+            "<function f(x)>"
+        ]
+    );
+}